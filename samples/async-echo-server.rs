@@ -0,0 +1,48 @@
+// The type aliases below mirror the type parameters of the runtime, so they keep its upper case names.
+#![allow(clippy::upper_case_acronyms)]
+
+use serde::Serialize;
+use Runtime_Aws_Lambda::prelude::*;
+
+// Requires the `hyper` feature of Runtime-Aws_Lambda and a `tokio` dependency with the `rt` and `macros` features.
+
+// Create a struct representing the lambda's response, and derive the [`serde::Serialize`] trait.
+#[derive(Serialize, Clone)]
+struct EchoMessage {
+    msg: String,
+    req_id: String,
+}
+
+// Define input, output and error types for berevity.
+type IN = serde_json::Value;
+type OUT = EchoMessage;
+type ERR = String;
+
+// The context object passed to async handlers shares ownership of the runtime's data,
+// so it may be moved into the handler's future.
+type CTX = ArcLambdaContext<LambdaRuntimeEnv, HyperResponse>;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // The initializer is a closure returning a future, which resolves to the event handler.
+    // The event handler is a closure returning a future, which resolves to the handler's result.
+    let mut runtime =
+        AsyncDefaultRuntime::<HyperResponse, HyperTransport, LambdaRuntimeEnv, IN, OUT, ERR>::new(
+            LAMBDA_VER,
+            || async {
+                Ok(|event: IN, context: CTX| async move {
+                    if event.as_str() == Some("") {
+                        return Err("Empty input, nothing to echo.".to_string());
+                    }
+
+                    // Echo the event back as a string.
+                    Ok(EchoMessage {
+                        msg: format!("ECHO: {}", event),
+                        req_id: context.aws_request_id().unwrap().to_string(),
+                    })
+                })
+            },
+        );
+
+    runtime.run().await;
+}
@@ -0,0 +1,47 @@
+// The type aliases below mirror the type parameters of the runtime, so they keep its upper case names.
+#![allow(clippy::upper_case_acronyms)]
+
+use std::io::Write;
+use Runtime_Aws_Lambda::prelude::*;
+use Runtime_Aws_Lambda::runtime::EventHandler;
+
+// Import the [`default_runtime`] macro from Runtime-Aws_Lambda.
+#[macro_use]
+extern crate Runtime_Aws_Lambda;
+
+// The event is a JSON number of messages to send.
+type IN = u32;
+// The output of a streaming handler is ignored once the stream is started.
+type OUT = ();
+type ERR = String;
+
+// Streams server-sent events back to the caller of a Function URL configured with the `RESPONSE_STREAM` invoke mode.
+fn initialize() -> Result<EventHandler<IN, LambdaRuntimeEnv, UreqResponse, OUT, ERR>, ERR> {
+    Ok(Box::new(move |count, context| {
+        // The response stream is handed to the handler through the context object.
+        let mut stream = match context.response_stream {
+            Some(stream) => stream,
+            None => return Err("Streaming is not supported by the runtime.".to_string()),
+        };
+
+        // Starting the stream explicitly sets its content type,
+        // otherwise the first write starts it as `application/octet-stream`.
+        stream
+            .start("text/event-stream")
+            .map_err(|err| err.to_string())?;
+
+        for i in 0..count {
+            // Each write is sent to the client as a separate chunk.
+            write!(stream, "data: message {}\n\n", i).map_err(|err| err.to_string())?;
+        }
+
+        // Errors returned after the stream was started are reported in its trailers.
+        Ok(())
+    }))
+}
+
+fn main() {
+    let mut runtime = default_runtime!(IN, OUT, ERR, LAMBDA_VER, initialize);
+
+    runtime.run();
+}
@@ -0,0 +1,188 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::response::*;
+use crate::error::Error;
+use crate::transport::AsyncTransport;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::{Method, Request, Response};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+use std::time::Duration;
+
+macro_rules! copy_str_header {
+    ($resp:expr, $header:expr) => {
+        $resp
+            .headers()
+            .get($header)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+}
+
+/// A wrapper that processes a [hyper::Response] and implements the [`crate::data::response::LambdaAPIResponse`] trait.
+pub struct HyperResponse {
+    body: Option<String>,
+    status: u16,
+    _request_id: Option<String>,
+    _deadline: Option<Duration>,
+    _arn: Option<String>,
+    _trace_id: Option<String>,
+    _cognito_id: Option<String>,
+    _client_context: Option<String>,
+}
+
+impl HyperResponse {
+    /// A constructor that consumes a [hyper::Response] by copying the relevant headers and reading the request body.
+    async fn from_response(resp: Response<Incoming>) -> Result<Self, Error> {
+        // Copy status
+        let status = resp.status().as_u16();
+
+        // Copy AWS headers, the request id is only sent along with events
+        let _request_id = copy_str_header!(resp, AWS_REQ_ID);
+
+        // Parse milliseconds to Duration
+        let _deadline = copy_str_header!(resp, AWS_DEADLINE_MS)
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .map(Duration::from_millis);
+        let _arn = copy_str_header!(resp, AWS_FUNC_ARN);
+        let _trace_id = copy_str_header!(resp, AWS_TRACE_ID);
+        let _cognito_id = copy_str_header!(resp, AWS_COG_ID);
+        let _client_context = copy_str_header!(resp, AWS_CLIENT_CTX);
+
+        // Consume the response into a string
+        let bytes = match resp.into_body().collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) => return Err(Error::transport("Failed reading response body", err)),
+        };
+        let body = match String::from_utf8(bytes.to_vec()) {
+            Ok(data) => Some(data),
+            Err(err) => return Err(Error::transport("Failed reading response body", err)),
+        };
+
+        Ok(Self {
+            body,
+            status,
+            _request_id,
+            _deadline,
+            _arn,
+            _trace_id,
+            _cognito_id,
+            _client_context,
+        })
+    }
+}
+
+impl LambdaAPIResponse for HyperResponse {
+    #[inline(always)]
+    fn get_body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    #[inline(always)]
+    fn get_status_code(&self) -> u16 {
+        self.status
+    }
+
+    #[inline]
+    fn aws_request_id(&self) -> Option<&str> {
+        self._request_id.as_deref()
+    }
+    #[inline]
+    fn deadline(&self) -> Option<Duration> {
+        self._deadline
+    }
+    #[inline]
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self._arn.as_deref()
+    }
+    #[inline]
+    fn trace_id(&self) -> Option<&str> {
+        self._trace_id.as_deref()
+    }
+    #[inline]
+    fn client_context(&self) -> Option<&str> {
+        self._client_context.as_deref()
+    }
+    #[inline]
+    fn cognito_identity(&self) -> Option<&str> {
+        self._cognito_id.as_deref()
+    }
+}
+
+/// Wraps a pooled [`hyper_util::client::legacy::Client`] to implement the [`crate::transport::AsyncTransport`] trait.
+/// Contains a specialized implementation for [`HyperResponse`] type parameter.
+///
+/// The client does not set a request timeout, as instructed by AWS for the next invocation call.
+/// Requests are driven by the [tokio](https://crates.io/crates/tokio) runtime the futures are awaited on.
+pub struct HyperTransport {
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl HyperTransport {
+    /// Creates a new transport object with an underlying HTTP/1 client executing on tokio.
+    fn new() -> Self {
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        HyperTransport { client }
+    }
+
+    /// Sends a request using the underlying client.
+    /// Responses with error status codes are returned as-is, leaving their handling to the runtime.
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<HyperResponse, Error> {
+        let mut req = Request::builder().method(method).uri(url);
+        if let Some(headers) = headers {
+            let (keys, values) = headers;
+            for (key, value) in keys.into_iter().zip(values) {
+                req = req.header(key, value);
+            }
+        }
+        let body = match body {
+            Some(body) => Full::new(Bytes::copy_from_slice(body.as_bytes())),
+            None => Full::default(),
+        };
+        let req = match req.body(body) {
+            Ok(req) => req,
+            Err(err) => return Err(Error::transport("Failed building request", err)),
+        };
+        match self.client.request(req).await {
+            Ok(resp) => HyperResponse::from_response(resp).await,
+            Err(err) => Err(Error::transport("Failed sending request", err)),
+        }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncTransport<HyperResponse> for HyperTransport {
+    async fn get(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<HyperResponse, Error> {
+        self.request(Method::GET, url, body, headers).await
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<HyperResponse, Error> {
+        self.request(Method::POST, url, body, headers).await
+    }
+}
@@ -5,3 +5,7 @@
 #[cfg(feature = "ureq")]
 /// An implementation for the [ureq](https://crates.io/crates/ureq) HTTP client.
 pub mod ureq;
+
+#[cfg(feature = "hyper")]
+/// An asynchronous implementation for the [hyper](https://crates.io/crates/hyper) HTTP client running on [tokio](https://crates.io/crates/tokio).
+pub mod hyper;
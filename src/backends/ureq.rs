@@ -4,10 +4,12 @@
 
 use crate::data::response::*;
 use crate::error::Error;
-use crate::transport::Transport;
+use crate::transport::{ChunkedRequest, Transport};
 use ureq::Agent;
 use ureq::Response;
 
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
 use std::time::Duration;
 
 macro_rules! copy_str_header {
@@ -20,7 +22,7 @@ macro_rules! copy_str_header {
 pub struct UreqResponse {
     body: Option<String>,
     status: u16,
-    _request_id: String,
+    _request_id: Option<String>,
     _deadline: Option<Duration>,
     _arn: Option<String>,
     _trace_id: Option<String>,
@@ -34,15 +36,8 @@ impl UreqResponse {
         // Copy status
         let status = resp.status();
 
-        // Copy AWS headers
-        let _request_id = match resp.header(AWS_REQ_ID) {
-            Some(v) => v.to_string(),
-            None => {
-                return Err(Error::new(
-                    "Missing Lambda-Runtime-Aws-Request-Id header".to_string(),
-                ))
-            }
-        };
+        // Copy AWS headers, the request id is only sent along with events
+        let _request_id = copy_str_header!(resp, AWS_REQ_ID);
 
         // Parse milliseconds to Duration
         let _deadline = match resp.header(AWS_DEADLINE_MS) {
@@ -60,7 +55,7 @@ impl UreqResponse {
         // Consume the response into a string
         let body = match resp.into_string() {
             Ok(data) => Some(data),
-            Err(err) => return Err(Error::new(format!("{}", err))),
+            Err(err) => return Err(Error::transport("Failed reading response body", err)),
         };
 
         Ok(Self {
@@ -89,7 +84,7 @@ impl LambdaAPIResponse for UreqResponse {
 
     #[inline]
     fn aws_request_id(&self) -> Option<&str> {
-        Some(&self._request_id)
+        self._request_id.as_deref()
     }
     #[inline]
     fn deadline(&self) -> Option<Duration> {
@@ -117,19 +112,25 @@ impl LambdaAPIResponse for UreqResponse {
 /// Contains a specialized implementation for [`UreqResponse`] type parameter.
 ///
 /// AWS runtime instructs the implementation to disable timeout on the next invocation call.
-/// This implementation achieves this by creating a [`ureq::Agent`] with 1 day in seconds of timeout.
+/// This implementation achieves this by creating a [`ureq::Agent`] with 1 day in seconds of timeout,
+/// which is overridden for POST requests by [`crate::transport::Transport::set_post_timeout`].
 pub struct UreqTransport {
     agent: Agent,
+    post_timeout: Option<Duration>,
 }
 
 impl UreqTransport {
     /// Creates a new transport objects with an underlying [ureq::Agent] that will (practically) not time out.
     fn new() -> Self {
         let agent = ureq::builder().timeout(Duration::from_secs(86400)).build();
-        UreqTransport { agent }
+        UreqTransport {
+            agent,
+            post_timeout: None,
+        }
     }
 
     /// Sends a request using the underlying agent.
+    /// Responses with error status codes are returned as-is, leaving their handling to the runtime.
     fn request(
         &self,
         method: &str,
@@ -138,6 +139,9 @@ impl UreqTransport {
         headers: Option<(Vec<&str>, Vec<&str>)>,
     ) -> Result<Response, Error> {
         let mut req = self.agent.request(method, url);
+        if let (Some(timeout), "POST") = (self.post_timeout, method) {
+            req = req.timeout(timeout);
+        }
         if let Some(headers) = headers {
             let (keys, values) = headers;
             let len = std::cmp::min(keys.len(), values.len());
@@ -145,12 +149,143 @@ impl UreqTransport {
                 req = req.set(keys[i], values[i]);
             }
         }
-        if let Some(body) = body {
-            return req
-                .send_string(body)
-                .map_err(|err| Error::new(format!("{}", err)));
+        let res = match body {
+            Some(body) => req.send_string(body),
+            None => req.call(),
+        };
+        match res {
+            Ok(resp) => Ok(resp),
+            Err(ureq::Error::Status(_, resp)) => Ok(resp),
+            Err(err) => Err(Error::transport("Failed sending request", err)),
+        }
+    }
+}
+
+/// A [`crate::transport::ChunkedRequest`] returned by [`UreqTransport`].
+///
+/// Since ureq does not support sending trailers, the request is written directly to a [`TcpStream`]
+/// - which is sufficient for the plain HTTP/1.1 runtime API - and the response is parsed by ureq once the request is finished.
+pub struct UreqChunkedRequest {
+    stream: TcpStream,
+}
+
+impl UreqChunkedRequest {
+    /// Connects to the host of the given `http://` url and writes the request head.
+    /// The optional `timeout` bounds connecting as well as each write of the body and reading the response.
+    fn start(
+        url: &str,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let addr = url.strip_prefix("http://").unwrap_or(url);
+        let (host, path) = match addr.find('/') {
+            Some(idx) => addr.split_at(idx),
+            None => (addr, "/"),
+        };
+        let mut stream = match connect(host, timeout) {
+            Ok(stream) => stream,
+            Err(err) => return Err(Error::transport("Failed connecting", err)),
+        };
+
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n",
+            path, host
+        );
+        if let Some((keys, values)) = headers {
+            for (key, value) in keys.iter().zip(values.iter()) {
+                push_header(&mut head, key, value)?;
+            }
+        }
+        head.push_str("\r\n");
+        if let Err(err) = stream.write_all(head.as_bytes()) {
+            return Err(Error::transport("Failed sending request", err));
+        }
+
+        Ok(Self { stream })
+    }
+}
+
+/// Appends a `key: value` header line to `out`.
+/// Names and values containing CR or LF are rejected, as writing them would corrupt the framing of the request.
+fn push_header(out: &mut String, key: &str, value: &str) -> Result<(), Error> {
+    if key.contains(['\r', '\n']) || value.contains(['\r', '\n']) {
+        return Err(Error::Transport {
+            msg: format!("Header {:?} contains a line break", key),
+            source: None,
+        });
+    }
+    out.push_str(&format!("{}: {}\r\n", key, value));
+    Ok(())
+}
+
+/// Connects to `host`, applying `timeout` to connecting and to every read and write on the connection.
+fn connect(host: &str, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect(host),
+    };
+    let mut last_err = None;
+    for addr in host.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Host did not resolve to any address",
+        )
+    }))
+}
+
+impl Write for UreqChunkedRequest {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // An empty chunk would terminate the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut chunk = format!("{:X}\r\n", buf.len()).into_bytes();
+        chunk.extend_from_slice(buf);
+        chunk.extend_from_slice(b"\r\n");
+        self.stream.write_all(&chunk)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl ChunkedRequest<UreqResponse> for UreqChunkedRequest {
+    fn finish(
+        mut self: Box<Self>,
+        trailers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<UreqResponse, Error> {
+        let mut tail = String::from("0\r\n");
+        if let Some((keys, values)) = trailers {
+            for (key, value) in keys.iter().zip(values.iter()) {
+                push_header(&mut tail, key, value)?;
+            }
+        }
+        tail.push_str("\r\n");
+        if let Err(err) = self.stream.write_all(tail.as_bytes()) {
+            return Err(Error::transport("Failed sending request", err));
+        }
+
+        // The request asks the server to close the connection, so the response ends with the stream
+        let mut raw = String::new();
+        if let Err(err) = self.stream.read_to_string(&mut raw) {
+            return Err(Error::transport("Failed reading response", err));
+        }
+        match raw.parse::<Response>() {
+            Ok(resp) => UreqResponse::from_response(resp),
+            Err(err) => Err(Error::transport("Failed parsing response", err)),
         }
-        req.call().map_err(|err| Error::new(format!("{}", err)))
     }
 }
 
@@ -186,4 +321,20 @@ impl Transport<UreqResponse> for UreqTransport {
         }
         Err(res.unwrap_err())
     }
+
+    fn post_chunked(
+        &self,
+        url: &str,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<Box<dyn ChunkedRequest<UreqResponse>>, Error> {
+        Ok(Box::new(UreqChunkedRequest::start(
+            url,
+            headers,
+            self.post_timeout,
+        )?))
+    }
+
+    fn set_post_timeout(&mut self, timeout: Option<Duration>) {
+        self.post_timeout = timeout;
+    }
 }
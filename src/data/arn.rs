@@ -0,0 +1,78 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::error::Error;
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The components of a Lambda function [ARN](https://docs.aws.amazon.com/lambda/latest/dg/lambda-api-permissions-ref.html),
+/// e.g: the ARN the function was invoked with: `arn:aws:lambda:us-east-1:123456789012:function:my-function:prod`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Arn {
+    /// The partition, e.g: `aws`, `aws-cn` or `aws-us-gov`.
+    pub partition: String,
+    pub region: String,
+    pub account_id: String,
+    pub function_name: String,
+    /// The version or alias the function was invoked with, `None` for unqualified ARNs.
+    pub qualifier: Option<String>,
+}
+
+impl Arn {
+    /// Returns the qualifier if it is a published version number or `$LATEST`.
+    pub fn version(&self) -> Option<&str> {
+        self.qualifier
+            .as_deref()
+            .filter(|qualifier| is_version(qualifier))
+    }
+
+    /// Returns the qualifier if it is an alias, e.g: `prod` or `canary`.
+    pub fn alias(&self) -> Option<&str> {
+        self.qualifier
+            .as_deref()
+            .filter(|qualifier| !is_version(qualifier))
+    }
+}
+
+fn is_version(qualifier: &str) -> bool {
+    qualifier == "$LATEST" || qualifier.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+impl FromStr for Arn {
+    type Err = Error;
+
+    /// Parses a Lambda function ARN, failing with [`Error::InvalidArn`] for malformed ARNs or ARNs of other resources.
+    fn from_str(arn: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = arn.split(':').collect();
+        match parts.as_slice() {
+            ["arn", partition, "lambda", region, account_id, "function", function_name, qualifier @ ..]
+                if qualifier.len() <= 1 && !parts.iter().any(|part| part.is_empty()) =>
+            {
+                Ok(Arn {
+                    partition: partition.to_string(),
+                    region: region.to_string(),
+                    account_id: account_id.to_string(),
+                    function_name: function_name.to_string(),
+                    qualifier: qualifier.first().map(|qualifier| qualifier.to_string()),
+                })
+            }
+            _ => Err(Error::InvalidArn(arn.to_string())),
+        }
+    }
+}
+
+impl Display for Arn {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "arn:{}:lambda:{}:{}:function:{}",
+            self.partition, self.region, self.account_id, self.function_name
+        )?;
+        match &self.qualifier {
+            Some(qualifier) => write!(f, ":{}", qualifier),
+            None => Ok(()),
+        }
+    }
+}
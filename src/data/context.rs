@@ -2,11 +2,21 @@
 
 // `SPDX-License-Identifier: MIT OR Apache-2.0`
 
+use crate::data::arn::Arn;
 use crate::data::env::RuntimeEnvVars;
-use crate::data::response::LambdaAPIResponse;
+use crate::data::extensions::{Extensions, SharedState};
+use crate::data::identity::{ClientContext, CognitoIdentity};
+use crate::data::response::{LambdaAPIResponse, AWS_DEADLINE_MS};
+use crate::data::trace::TraceHeader;
 use crate::error::Error;
+use crate::runtime::cancel::CancellationToken;
+use crate::runtime::stream::ResponseStream;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// The header propagating the trace id of an invocation to downstream services, see [`LambdaContext::trace_header`].
+pub static TRACE_HEADER: &str = "X-Amzn-Trace-Id";
+
 /// An interface trait that should be implemented by types representing a [Context object]([https://docs.aws.amazon.com/lambda/latest/dg/python-context.html]).
 ///
 /// The context object exposes constant data from the instance's environment variables,
@@ -19,17 +29,21 @@ pub trait LambdaContext {
         match now.duration_since(std::time::SystemTime::UNIX_EPOCH) {
             Ok(now_since_epoch) => match self.get_deadline() {
                 Some(dur) => dur,
-                None => return Err(Error::new("Missing deadline info".to_string())),
+                None => return Err(Error::MissingHeader(AWS_DEADLINE_MS)),
             }
             .checked_sub(now_since_epoch)
-            .ok_or_else(|| Error::new("Duration error".to_string())),
-            Err(e) => Err(Error::new(e.to_string())),
+            .ok_or_else(|| Error::Timeout("Deadline has already passed".to_string())),
+            Err(e) => Err(Error::Timeout(e.to_string())),
         }
     }
     // Per-invocation data (event-related)
     fn get_deadline(&self) -> Option<Duration>;
     fn invoked_function_arn(&self) -> Option<&str>;
     fn aws_request_id(&self) -> Option<&str>;
+    /// The X-Ray trace id of the invocation. Returns `None` by default, for context types that do not expose it.
+    fn trace_id(&self) -> Option<&str> {
+        None
+    }
     // Per-runtime data (constant accross the lifetime of the runtime, taken from env-vars)
     fn function_name(&self) -> Option<&str>;
     fn function_version(&self) -> Option<&str>;
@@ -37,9 +51,56 @@ pub trait LambdaContext {
     fn log_group_name(&self) -> Option<&str>;
     fn log_stream_name(&self) -> Option<&str>;
     // Identity and Client context - see [https://docs.aws.amazon.com/lambda/latest/dg/python-context.html]
-    // TODO - parse these structures and return a relevant type
+    /// The raw JSON value of the `Lambda-Runtime-Cognito-Identity` header, see [`LambdaContext::parse_cognito_identity`].
     fn cognito_identity(&self) -> Option<&str>;
+    /// The raw JSON value of the `Lambda-Runtime-Client-Context` header, see [`LambdaContext::parse_client_context`].
     fn client_context(&self) -> Option<&str>;
+    /// The token cancelled shortly before the invocation deadline. Returns `None` by default, for context types that do not expose it.
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        None
+    }
+    /// The state shared by all the invocations of the runtime. Returns `None` by default, for context types that do not expose it.
+    fn shared_state(&self) -> Option<&SharedState> {
+        None
+    }
+    /// The map for passing data between layers and the event handler. Returns `None` by default, for context types that do not expose it.
+    fn extensions(&self) -> Option<&Extensions> {
+        None
+    }
+
+    /// Returns the name and value of the header propagating the trace id to outbound HTTP calls, e.g:
+    /// `if let Some((name, value)) = context.trace_header() { request = request.set(name, value) }`.
+    fn trace_header(&self) -> Option<(&'static str, &str)> {
+        self.trace_id().map(|trace_id| (TRACE_HEADER, trace_id))
+    }
+
+    /// Parses the trace id of the invocation as an X-Ray tracing header, returns `Ok(None)` if it was not sent.
+    fn parse_trace_header(&self) -> Result<Option<TraceHeader>, Error> {
+        self.trace_id().map(str::parse).transpose()
+    }
+
+    /// Parses the ARN the function was invoked with, returns `Ok(None)` if it was not sent.
+    fn invoked_arn(&self) -> Result<Option<Arn>, Error> {
+        self.invoked_function_arn().map(str::parse).transpose()
+    }
+
+    /// Returns the version or alias the function was invoked with, e.g: `prod` or `canary`,
+    /// or `None` for unqualified invocations and malformed ARNs.
+    fn invoked_qualifier(&self) -> Option<String> {
+        self.invoked_arn().ok().flatten()?.qualifier
+    }
+
+    /// Parses the Cognito identity of the invocation on demand, returns `Ok(None)` if it was not sent.
+    fn parse_cognito_identity(&self) -> Result<Option<CognitoIdentity>, Error> {
+        self.cognito_identity()
+            .map(CognitoIdentity::parse)
+            .transpose()
+    }
+
+    /// Parses the client context of the invocation on demand, returns `Ok(None)` if it was not sent.
+    fn parse_client_context(&self) -> Result<Option<ClientContext>, Error> {
+        self.client_context().map(ClientContext::parse).transpose()
+    }
 }
 
 /// A generic implementation of [`LambdaContext`] that relies on **borrowing** existing owned
@@ -62,6 +123,32 @@ where
     pub env_vars: &'a E,
     /// A shared reference to a type implementing [`crate::data::response::LambdaAPIResponse`].
     pub invo_resp: &'a R,
+    /// A shared reference to the [`crate::runtime::stream::ResponseStream`] of the invocation, if the runtime supports streaming responses.
+    pub response_stream: Option<&'a ResponseStream<'a, R>>,
+    /// A token cancelled shortly before the invocation deadline, if the runtime was configured with a cancellation margin.
+    pub cancellation: Option<&'a CancellationToken>,
+    /// A map for passing data between layers and the event handler, created empty for each invocation.
+    pub extensions: &'a Extensions,
+    /// A map of values shared by all the invocations of the runtime, set while setting up the runtime.
+    pub shared_state: &'a SharedState,
+}
+
+impl<'a, E, R> Clone for RefLambdaContext<'a, E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// The context only holds shared references, so copying it is cheap.
+impl<'a, E, R> Copy for RefLambdaContext<'a, E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
 }
 
 impl<'a, E, R> LambdaContext for RefLambdaContext<'a, E, R>
@@ -84,6 +171,167 @@ where
         self.invo_resp.aws_request_id()
     }
 
+    #[inline(always)]
+    fn trace_id(&self) -> Option<&str> {
+        self.invo_resp.trace_id()
+    }
+
+    #[inline(always)]
+    fn function_name(&self) -> Option<&str> {
+        self.env_vars.get_function_name()
+    }
+
+    #[inline(always)]
+    fn function_version(&self) -> Option<&str> {
+        self.env_vars.get_function_version()
+    }
+
+    #[inline(always)]
+    fn memory_limit_in_mb(&self) -> Option<usize> {
+        self.env_vars.get_function_memory_size()
+    }
+
+    #[inline(always)]
+    fn log_group_name(&self) -> Option<&str> {
+        self.env_vars.get_log_group_name()
+    }
+
+    #[inline(always)]
+    fn log_stream_name(&self) -> Option<&str> {
+        self.env_vars.get_log_stream_name()
+    }
+
+    #[inline(always)]
+    fn cognito_identity(&self) -> Option<&str> {
+        self.invo_resp.cognito_identity()
+    }
+
+    #[inline(always)]
+    fn client_context(&self) -> Option<&str> {
+        self.invo_resp.client_context()
+    }
+
+    #[inline(always)]
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation
+    }
+
+    #[inline(always)]
+    fn shared_state(&self) -> Option<&SharedState> {
+        Some(self.shared_state)
+    }
+
+    #[inline(always)]
+    fn extensions(&self) -> Option<&Extensions> {
+        Some(self.extensions)
+    }
+}
+
+/// Creates the context object passed to the event handler of a [`crate::runtime::DefaultRuntime`] for each invocation,
+/// letting applications supply their own context type - e.g: with per-request fields such as a tenant or parsed headers.
+///
+/// The factory receives a [`RefLambdaContext`] borrowing the env-vars, the invocation response, the response stream and the cancellation token,
+/// which the created context may keep or copy from. [`RefContextFactory`] is the default factory, passing it on as is.
+pub trait ContextFactory<E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    /// The context type, which may borrow the data of the invocation for lifetime `'a`.
+    type Context<'a>: LambdaContext
+    where
+        E: 'a,
+        R: 'a;
+
+    /// Creates the context object of an invocation.
+    fn create<'a>(&self, base: RefLambdaContext<'a, E, R>) -> Self::Context<'a>;
+}
+
+/// The default [`ContextFactory`], passing the [`RefLambdaContext`] created by the runtime to the event handler.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RefContextFactory;
+
+impl<E, R> ContextFactory<E, R> for RefContextFactory
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    type Context<'a>
+        = RefLambdaContext<'a, E, R>
+    where
+        E: 'a,
+        R: 'a;
+
+    #[inline(always)]
+    fn create<'a>(&self, base: RefLambdaContext<'a, E, R>) -> Self::Context<'a> {
+        base
+    }
+}
+
+/// A generic implementation of [`LambdaContext`] that **shares** ownership of the instances of types implementing
+/// [`crate::data::env::RuntimeEnvVars`] and [`crate::data::response::LambdaAPIResponse`] using [`Arc`].
+///
+/// Unlike [`RefLambdaContext`] it is not bound to a lifetime, making it suitable for async event handlers
+/// whose futures may outlive the borrow of the runtime, as used by the `AsyncDefaultRuntime`.
+/// Cloning the context is cheap as it only clones the underlying [`Arc`] pointers.
+///
+/// It exposes the runtime's [`SharedState`], but not the per-invocation [`Extensions`] -
+/// which pass data between the layers of a [`crate::runtime::DefaultRuntime`] and its handler, while the async runtime has no layer stack.
+pub struct ArcLambdaContext<E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    /// A shared pointer to a type implementing [`crate::data::env::RuntimeEnvVars`].
+    pub env_vars: Arc<E>,
+    /// A shared pointer to a type implementing [`crate::data::response::LambdaAPIResponse`].
+    pub invo_resp: Arc<R>,
+    /// A token cancelled shortly before the invocation deadline, if the runtime was configured with a cancellation margin.
+    pub cancellation: Option<CancellationToken>,
+    /// A shared pointer to the map of values shared by all the invocations of the runtime.
+    pub shared_state: Arc<SharedState>,
+}
+
+impl<E, R> Clone for ArcLambdaContext<E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    fn clone(&self) -> Self {
+        Self {
+            env_vars: Arc::clone(&self.env_vars),
+            invo_resp: Arc::clone(&self.invo_resp),
+            cancellation: self.cancellation.clone(),
+            shared_state: Arc::clone(&self.shared_state),
+        }
+    }
+}
+
+impl<E, R> LambdaContext for ArcLambdaContext<E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    #[inline]
+    fn get_deadline(&self) -> Option<Duration> {
+        self.invo_resp.deadline()
+    }
+
+    #[inline(always)]
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self.invo_resp.invoked_function_arn()
+    }
+
+    #[inline(always)]
+    fn aws_request_id(&self) -> Option<&str> {
+        self.invo_resp.aws_request_id()
+    }
+
+    #[inline(always)]
+    fn trace_id(&self) -> Option<&str> {
+        self.invo_resp.trace_id()
+    }
+
     #[inline(always)]
     fn function_name(&self) -> Option<&str> {
         self.env_vars.get_function_name()
@@ -118,4 +366,158 @@ where
     fn client_context(&self) -> Option<&str> {
         self.invo_resp.client_context()
     }
+
+    #[inline(always)]
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    #[inline(always)]
+    fn shared_state(&self) -> Option<&SharedState> {
+        Some(&self.shared_state)
+    }
+}
+
+/// An owned snapshot of a context object, implementing [`LambdaContext`] by copying its data.
+///
+/// Unlike [`RefLambdaContext`] it is [`Send`] and [`Sync`] and is not bound to a lifetime,
+/// so it can be moved to worker threads spawned by the event handler or stored beyond the handler call.
+/// It is created from the context object passed to the handler, e.g: using [`RefLambdaContext::into_owned`].
+#[derive(Clone, Default)]
+pub struct OwnedLambdaContext {
+    pub deadline: Option<Duration>,
+    pub invoked_function_arn: Option<String>,
+    pub aws_request_id: Option<String>,
+    pub trace_id: Option<String>,
+    pub function_name: Option<String>,
+    pub function_version: Option<String>,
+    pub memory_limit_in_mb: Option<usize>,
+    pub log_group_name: Option<String>,
+    pub log_stream_name: Option<String>,
+    pub cognito_identity: Option<String>,
+    pub client_context: Option<String>,
+    /// A clone of the token of the context it was created from, cancelled along with it.
+    pub cancellation: Option<CancellationToken>,
+    /// A clone of the shared state of the context it was created from.
+    pub shared_state: SharedState,
+}
+
+impl OwnedLambdaContext {
+    /// Copies the data of any [`LambdaContext`] implementation, cloning its cancellation token and shared state if it exposes them.
+    pub fn from_context<C: LambdaContext + ?Sized>(context: &C) -> Self {
+        let to_string = |value: Option<&str>| value.map(str::to_string);
+        OwnedLambdaContext {
+            deadline: context.get_deadline(),
+            invoked_function_arn: to_string(context.invoked_function_arn()),
+            aws_request_id: to_string(context.aws_request_id()),
+            trace_id: to_string(context.trace_id()),
+            function_name: to_string(context.function_name()),
+            function_version: to_string(context.function_version()),
+            memory_limit_in_mb: context.memory_limit_in_mb(),
+            log_group_name: to_string(context.log_group_name()),
+            log_stream_name: to_string(context.log_stream_name()),
+            cognito_identity: to_string(context.cognito_identity()),
+            client_context: to_string(context.client_context()),
+            cancellation: context.cancellation().cloned(),
+            shared_state: context.shared_state().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl<'a, E, R> RefLambdaContext<'a, E, R>
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    /// Creates an [`OwnedLambdaContext`] snapshot of the context.
+    pub fn into_owned(self) -> OwnedLambdaContext {
+        OwnedLambdaContext::from(self)
+    }
+}
+
+impl<'a, E, R> From<RefLambdaContext<'a, E, R>> for OwnedLambdaContext
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    fn from(context: RefLambdaContext<'a, E, R>) -> Self {
+        OwnedLambdaContext::from_context(&context)
+    }
+}
+
+impl<E, R> From<&ArcLambdaContext<E, R>> for OwnedLambdaContext
+where
+    E: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+{
+    fn from(context: &ArcLambdaContext<E, R>) -> Self {
+        OwnedLambdaContext::from_context(context)
+    }
+}
+
+impl LambdaContext for OwnedLambdaContext {
+    #[inline(always)]
+    fn get_deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    #[inline(always)]
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self.invoked_function_arn.as_deref()
+    }
+
+    #[inline(always)]
+    fn aws_request_id(&self) -> Option<&str> {
+        self.aws_request_id.as_deref()
+    }
+
+    #[inline(always)]
+    fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
+    #[inline(always)]
+    fn function_name(&self) -> Option<&str> {
+        self.function_name.as_deref()
+    }
+
+    #[inline(always)]
+    fn function_version(&self) -> Option<&str> {
+        self.function_version.as_deref()
+    }
+
+    #[inline(always)]
+    fn memory_limit_in_mb(&self) -> Option<usize> {
+        self.memory_limit_in_mb
+    }
+
+    #[inline(always)]
+    fn log_group_name(&self) -> Option<&str> {
+        self.log_group_name.as_deref()
+    }
+
+    #[inline(always)]
+    fn log_stream_name(&self) -> Option<&str> {
+        self.log_stream_name.as_deref()
+    }
+
+    #[inline(always)]
+    fn cognito_identity(&self) -> Option<&str> {
+        self.cognito_identity.as_deref()
+    }
+
+    #[inline(always)]
+    fn client_context(&self) -> Option<&str> {
+        self.client_context.as_deref()
+    }
+
+    #[inline(always)]
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    #[inline(always)]
+    fn shared_state(&self) -> Option<&SharedState> {
+        Some(&self.shared_state)
+    }
 }
@@ -2,6 +2,9 @@
 
 // `SPDX-License-Identifier: MIT OR Apache-2.0`
 
+/// The env-var holding the trace id of the current invocation, set by the runtime only if enabled, e.g: using [`crate::runtime::DefaultRuntime::set_trace_env_var`].
+pub static TRACE_ID_ENV_VAR: &str = "_X_AMZN_TRACE_ID";
+
 /// An enum representing the `InitializationType` choices set as an env-var on the instance by AWS Lambda.
 /// See [Defined runtime environment variables](https://docs.aws.amazon.com/lambda/latest/dg/configuration-envvars.html#configuration-envvars-runtime).
 #[derive(Clone, Copy, Debug)]
@@ -0,0 +1,124 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A map holding a single value of each type, attached to the context object of a single invocation.
+///
+/// Layers and the event handler use it to pass data to each other - e.g: parsed auth claims or a DB transaction,
+/// it is created empty for each invocation and dropped once the invocation ends.
+/// Values are inserted and read through a shared reference, so it can be used from the context.
+#[derive(Default)]
+pub struct Extensions {
+    map: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value, returning the previous value of the same type if there was one.
+    pub fn insert<T: 'static>(&self, value: T) -> Option<T> {
+        self.map
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.downcast().ok())
+            .map(|prev| *prev)
+    }
+
+    /// Returns a reference to the value of type `T`.
+    ///
+    /// The map stays borrowed while the reference is held, inserting or removing values in the meantime panics.
+    pub fn get<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.map.borrow(), |map| {
+            map.get(&TypeId::of::<T>())
+                .and_then(|value| value.downcast_ref())
+        })
+        .ok()
+    }
+
+    /// Returns a mutable reference to the value of type `T`.
+    ///
+    /// The map stays borrowed while the reference is held, accessing it in the meantime panics.
+    pub fn get_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.map.borrow_mut(), |map| {
+            map.get_mut(&TypeId::of::<T>())
+                .and_then(|value| value.downcast_mut())
+        })
+        .ok()
+    }
+
+    /// Removes and returns the value of type `T`.
+    pub fn remove<T: 'static>(&self) -> Option<T> {
+        self.map
+            .borrow_mut()
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Returns `true` if the map holds a value of type `T`.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.map.borrow().contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.borrow().is_empty()
+    }
+}
+
+/// A map holding a single value of each type, shared by all the invocations of a runtime - e.g: a DB connection pool or configuration.
+///
+/// It is populated while setting up the runtime and is read-only while processing events,
+/// values are stored behind an [`Arc`] so they can be handed to worker threads using [`SharedState::get_arc`].
+#[derive(Clone, Default)]
+pub struct SharedState {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl SharedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value, replacing the previous value of the same type if there was one.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns a reference to the value of type `T`.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns a shared pointer to the value of type `T`.
+    pub fn get_arc<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| Arc::clone(value).downcast().ok())
+    }
+
+    /// Returns `true` if the map holds a value of type `T`.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::response::{AWS_CLIENT_CTX, AWS_COG_ID};
+use crate::error::Error;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Information about the client application and device that invoked the function through the AWS Mobile SDK,
+/// sent as JSON in the `Lambda-Runtime-Client-Context` header.
+///
+/// See the `client_context` attribute of the [context object](https://docs.aws.amazon.com/lambda/latest/dg/python-context.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientContext {
+    /// Details about the client application, e.g: `installation_id`, `app_title`, `app_version_name`, `app_version_code` and `app_package_name`.
+    pub client: HashMap<String, String>,
+    /// Custom values set by the mobile client application, which may be any JSON value.
+    pub custom: HashMap<String, Value>,
+    /// Environment information provided by the AWS SDK, e.g: `platform`, `platform_version`, `make`, `model` and `locale`.
+    pub env: HashMap<String, String>,
+}
+
+impl ClientContext {
+    /// Parses the value of the `Lambda-Runtime-Client-Context` header.
+    pub fn parse(header: &str) -> Result<Self, Error> {
+        serde_json::from_str(header).map_err(|source| Error::InvalidHeader {
+            header: AWS_CLIENT_CTX,
+            source,
+        })
+    }
+}
+
+/// Information about the Amazon Cognito identity that authorized the request,
+/// sent as JSON in the `Lambda-Runtime-Cognito-Identity` header.
+///
+/// See the `identity` attribute of the [context object](https://docs.aws.amazon.com/lambda/latest/dg/python-context.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CognitoIdentity {
+    /// The authenticated Amazon Cognito identity.
+    #[serde(rename = "cognitoIdentityId", alias = "identity_id")]
+    pub identity_id: String,
+    /// The Amazon Cognito identity pool that authorized the invocation.
+    #[serde(rename = "cognitoIdentityPoolId", alias = "identity_pool_id")]
+    pub identity_pool_id: String,
+}
+
+impl CognitoIdentity {
+    /// Parses the value of the `Lambda-Runtime-Cognito-Identity` header.
+    pub fn parse(header: &str) -> Result<Self, Error> {
+        serde_json::from_str(header).map_err(|source| Error::InvalidHeader {
+            header: AWS_COG_ID,
+            source,
+        })
+    }
+}
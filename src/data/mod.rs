@@ -2,9 +2,17 @@
 
 // `SPDX-License-Identifier: MIT OR Apache-2.0`
 
+/// Defines the [`crate::data::arn::Arn`] type parsed from the ARN a function was invoked with.
+pub mod arn;
 /// Defines the interface of the context object and provides an implementation for it.
 pub mod context;
 /// Defines an interface for reading env-vars and provides an implementation for it.
 pub mod env;
+/// Defines the per-invocation and container-wide type maps exposed by the context object.
+pub mod extensions;
+/// Defines the client context and Cognito identity types parsed from the headers of mobile invocations.
+pub mod identity;
 /// Defines the interface used to read a response from the Lambda API.
 pub mod response;
+/// Defines the [`crate::data::trace::TraceHeader`] type parsed from the trace id of an invocation.
+pub mod trace;
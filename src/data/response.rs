@@ -11,6 +11,8 @@ pub static AWS_TRACE_ID: &str = "Lambda-Runtime-Trace-Id";
 pub static AWS_CLIENT_CTX: &str = "Lambda-Runtime-Client-Context";
 pub static AWS_COG_ID: &str = "Lambda-Runtime-Cognito-Identity";
 pub static AWS_FUNC_ERR_TYPE: &str = "Lambda-Runtime-Function-Error-Type";
+pub static AWS_FUNC_ERR_BODY: &str = "Lambda-Runtime-Function-Error-Body";
+pub static AWS_FUNC_RESPONSE_MODE: &str = "Lambda-Runtime-Function-Response-Mode";
 
 //Based on [https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-next]
 /// An interface trait representing a response from the [AWS Lambda runtime API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
@@ -70,6 +72,6 @@ pub trait LambdaAPIResponse {
     }
 
     fn is_err(&self) -> bool {
-        matches!(self.get_status_code(), 400..=499 | 500..=599)
+        matches!(self.get_status_code(), 400..=599)
     }
 }
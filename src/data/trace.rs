@@ -0,0 +1,72 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::error::Error;
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The fields of an [X-Ray tracing header](https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader),
+/// e.g: the trace id of an invocation: `Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TraceHeader {
+    /// The id of the trace.
+    pub root: String,
+    /// The id of the parent segment, the function's segment for the trace id of an invocation.
+    pub parent: Option<String>,
+    /// The sampling decision, `None` if it was not made yet (`Sampled=?`) or is missing.
+    pub sampled: Option<bool>,
+}
+
+impl TraceHeader {
+    /// Returns a header for a call made within the segment or subsegment `parent`, e.g: to propagate it to a downstream service.
+    pub fn with_parent(&self, parent: &str) -> Self {
+        TraceHeader {
+            parent: Some(parent.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+impl FromStr for TraceHeader {
+    type Err = Error;
+
+    /// Parses a tracing header, failing with [`Error::InvalidTraceHeader`] if it has no `Root` field or an invalid `Sampled` field.
+    /// Any other field (e.g: `Lineage`) is ignored.
+    fn from_str(header: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidTraceHeader(header.to_string());
+        let (mut root, mut parent, mut sampled) = (None, None, None);
+        for field in header.split(';').map(str::trim).filter(|f| !f.is_empty()) {
+            match field.split_once('=').ok_or_else(invalid)? {
+                ("Root", value) if !value.is_empty() => root = Some(value.to_string()),
+                ("Parent", value) if !value.is_empty() => parent = Some(value.to_string()),
+                ("Sampled", "1") => sampled = Some(true),
+                ("Sampled", "0") => sampled = Some(false),
+                ("Sampled", "?") => sampled = None,
+                ("Root" | "Parent" | "Sampled", _) => return Err(invalid()),
+                _ => (),
+            }
+        }
+        Ok(TraceHeader {
+            root: root.ok_or_else(invalid)?,
+            parent,
+            sampled,
+        })
+    }
+}
+
+impl Display for TraceHeader {
+    /// Formats the header value, e.g: to be sent as the `X-Amzn-Trace-Id` header.
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Root={}", self.root)?;
+        if let Some(parent) = &self.parent {
+            write!(f, ";Parent={}", parent)?;
+        }
+        match self.sampled {
+            Some(true) => write!(f, ";Sampled=1"),
+            Some(false) => write!(f, ";Sampled=0"),
+            None => Ok(()),
+        }
+    }
+}
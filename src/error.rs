@@ -2,23 +2,219 @@
 
 // `SPDX-License-Identifier: MIT OR Apache-2.0`
 
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-#[derive(Clone, Debug)]
-pub struct Error {
-    msg: String,
+/// The error type returned by the runtime, transport and context APIs.
+///
+/// Errors raised by underlying libraries (e.g: the HTTP backend or [`serde_json`]) are chained and available through [`std::error::Error::source`].
+/// New variants may be added in minor releases, so matches on it should include a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The runtime API responded with a server error (5xx), signaling that the container is in a non-recoverable state and should exit.
+    Container { status: u16 },
+    /// The runtime API responded with a client error (4xx), along with the parsed [`ErrorResponse`] body if one was sent.
+    Http {
+        status: u16,
+        body: Option<ErrorResponse>,
+    },
+    /// The HTTP backend failed to send a request or read its response.
+    Transport {
+        msg: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// Serializing or deserializing JSON failed.
+    Serialization(serde_json::Error),
+    /// A required header was missing from a runtime API response.
+    MissingHeader(&'static str),
+    /// A header of a runtime API response could not be parsed.
+    InvalidHeader {
+        header: &'static str,
+        source: serde_json::Error,
+    },
+    /// An ARN could not be parsed as a Lambda function ARN.
+    InvalidArn(String),
+    /// A trace id could not be parsed as an X-Ray tracing header.
+    InvalidTraceHeader(String),
+    /// A required environment variable was missing or invalid.
+    Env(String),
+    /// The invocation deadline has passed or could not be calculated.
+    Timeout(String),
+    /// The event handler failed, described by the [`ErrorRequest`] reported to the Lambda service.
+    Handler(ErrorRequest),
 }
 
 impl Error {
-    pub fn new(msg: String) -> Self {
-        Error { msg }
+    /// Creates an [`Error::Transport`] chaining the error raised by the HTTP backend.
+    pub fn transport<E>(msg: &str, source: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Error::Transport {
+            msg: msg.to_string(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// Returns `true` if the error signals a non-recoverable state of the container.
+    #[inline]
+    pub fn is_container_err(&self) -> bool {
+        matches!(self, Error::Container { .. })
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "{}", &self.msg)
+        match self {
+            Error::Container { status } => write!(f, "{} Status: {}", CONTAINER_ERR, status),
+            Error::Http { status, body } => match body {
+                Some(resp) => write!(
+                    f,
+                    "Client error ({}). ErrorResponse: {}: {}",
+                    status, resp.error_type, resp.error_message
+                ),
+                None => write!(f, "Client error ({}).", status),
+            },
+            Error::Transport { msg, source } => match source {
+                Some(source) => write!(f, "Transport error. {}: {}", msg, source),
+                None => write!(f, "Transport error. {}", msg),
+            },
+            Error::Serialization(err) => write!(f, "Serialization error. {}", err),
+            Error::MissingHeader(header) => write!(f, "Missing {} header", header),
+            Error::InvalidHeader { header, source } => {
+                write!(f, "Invalid {} header. {}", header, source)
+            }
+            Error::InvalidArn(arn) => write!(f, "Invalid function ARN: {}", arn),
+            Error::InvalidTraceHeader(header) => write!(f, "Invalid trace header: {}", header),
+            Error::Env(msg) => write!(f, "Environment error. {}", msg),
+            Error::Timeout(msg) => write!(f, "Timeout error. {}", msg),
+            Error::Handler(req) => {
+                write!(
+                    f,
+                    "Handler error. {}: {}",
+                    req.error_type, req.error_message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            Error::Serialization(err) => Some(err),
+            Error::InvalidHeader { source, .. } => Some(source),
+            _ => None,
+        }
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serialization(err)
+    }
+}
+
+/// The JSON body returned by the runtime API along with a client error status code.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ErrorResponse {
+    pub error_message: String,
+    pub error_type: String,
+}
+
 pub static CONTAINER_ERR: &str = "Container error. Non-recoverable state.";
+
+/// The error type reported to the Lambda service when an event could not be deserialized.
+pub static UNMARSHAL_ERR_TYPE: &str = "Runtime.UnmarshalError";
+
+/// The error type reported to the Lambda service when the initializer fails, unless overridden by [`LambdaError::error_type`].
+pub static INIT_ERR_TYPE: &str = "Runtime.InitError";
+
+/// The error type reported to the Lambda service when the event handler fails, unless overridden by [`LambdaError::error_type`].
+pub static HANDLER_ERR_TYPE: &str = "Runtime.HandlerError";
+
+/// The error type reported to the Lambda service when the event handler panics.
+pub static PANIC_ERR_TYPE: &str = "Runtime.Panic";
+
+/// The error type reported to the Lambda service when an invocation is missing its request id.
+pub static MISSING_REQ_ID_ERR_TYPE: &str = "Runtime.MissingRequestId";
+
+/// The error type reported to the Lambda service when the response of the event handler could not be serialized.
+pub static SERIALIZATION_ERR_TYPE: &str = "Runtime.SerializationError";
+
+/// The JSON body sent to the Lambda service when reporting an [initialization](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-initerror)
+/// or an [invocation](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-invokeerror) error.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRequest {
+    pub error_message: String,
+    pub error_type: String,
+    pub stack_trace: Vec<String>,
+}
+
+impl ErrorRequest {
+    /// Creates an [`ErrorRequest`] with the given type and message and an empty stack trace.
+    pub fn new(error_type: &str, error_message: &str) -> Self {
+        ErrorRequest {
+            error_message: error_message.to_string(),
+            error_type: error_type.to_string(),
+            stack_trace: Vec::new(),
+        }
+    }
+
+    /// Creates an [`ErrorRequest`] from a type implementing [`LambdaError`],
+    /// using `default_type` if the error does not define its own error type.
+    pub fn from_error<E: LambdaError + ?Sized>(err: &E, default_type: &str) -> Self {
+        ErrorRequest {
+            error_message: err.error_message(),
+            error_type: err.error_type().unwrap_or(default_type).to_string(),
+            stack_trace: err.stack_trace(),
+        }
+    }
+}
+
+/// An interface trait implemented by user-defined error types returned from the initializer or the event handler,
+/// describing how the error is reported to the Lambda service.
+///
+/// The reported `errorType` is also set as the `Lambda-Runtime-Function-Error-Type` header,
+/// making it visible to CloudWatch and to Step Functions retry and catch policies.
+pub trait LambdaError: Display {
+    /// The reported error type. Returns `None` by default, in which case the runtime reports
+    /// [`INIT_ERR_TYPE`] or [`HANDLER_ERR_TYPE`] depending on where the error occurred.
+    fn error_type(&self) -> Option<&str> {
+        None
+    }
+
+    /// The reported error message. Defaults to the [`Display`] representation of the error.
+    fn error_message(&self) -> String {
+        self.to_string()
+    }
+
+    /// The reported stack trace. Empty by default.
+    fn stack_trace(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+impl LambdaError for String {}
+
+impl LambdaError for &str {}
+
+/// Reports the chain of [`std::error::Error::source`] errors as the stack trace.
+impl LambdaError for Box<dyn std::error::Error + Send + Sync> {
+    fn stack_trace(&self) -> Vec<String> {
+        let mut trace = Vec::new();
+        let mut source = self.source();
+        while let Some(err) = source {
+            trace.push(err.to_string());
+            source = err.source();
+        }
+        trace
+    }
+}
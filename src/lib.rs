@@ -10,6 +10,9 @@ pub mod data;
 pub mod error;
 /// Defines the [`crate::runtime::LambdaRuntime`] API and provides a default generic implementation.
 pub mod runtime;
+/// Utilities for testing runtimes and event handlers without a Lambda environment.
+#[cfg(feature = "testing")]
+pub mod testing;
 /// Defines the [`crate::transport::Transport`] abstraction used to support multiple HTTP backends.
 pub mod transport;
 
@@ -19,29 +22,48 @@ pub static LAMBDA_VER: &str = "2018-06-01";
 /// A prelude that contains all the relevant imports when using the library's default runtime implementation,
 /// which currently ships with a [ureq](https://crates.io/crates/ureq) based HTTP Backend and [serde_json](https://crates.io/crates/serde_json) for serialization.
 pub mod prelude {
+    #[cfg(feature = "hyper")]
+    pub use crate::backends::hyper::*;
+    #[cfg(feature = "ureq")]
     pub use crate::backends::ureq::*;
-    pub use crate::data::context::{LambdaContext, RefLambdaContext};
+    pub use crate::data::arn::Arn;
+    pub use crate::data::context::{
+        ArcLambdaContext, ContextFactory, LambdaContext, OwnedLambdaContext, RefContextFactory,
+        RefLambdaContext,
+    };
     pub use crate::data::env::LambdaRuntimeEnv;
-    pub use crate::runtime::{DefaultRuntime, LambdaRuntime};
+    pub use crate::data::extensions::{Extensions, SharedState};
+    pub use crate::data::identity::{ClientContext, CognitoIdentity};
+    pub use crate::data::trace::TraceHeader;
+    pub use crate::error::{ErrorRequest, LambdaError};
+    #[cfg(feature = "async")]
+    pub use crate::runtime::async_runtime::{AsyncDefaultRuntime, AsyncLambdaRuntime};
+    pub use crate::runtime::builder::RuntimeBuilder;
+    pub use crate::runtime::cancel::CancellationToken;
+    pub use crate::runtime::handler::Handler;
+    pub use crate::runtime::layer::{Layer, Next};
+    pub use crate::runtime::retry::RetryPolicy;
+    pub use crate::runtime::{DefaultRuntime, InvocationOutcome, LambdaRuntime, ShutdownHandle};
     pub use crate::LAMBDA_VER;
 }
 
-/// Creates a [`crate::runtime::DefaultRuntime`] with the given response, transport, env, out, err types as well as version and initializer.
+/// Creates a [`crate::runtime::DefaultRuntime`] with the given response, transport, env, in, out, err types as well as version and initializer.
 #[macro_export]
 macro_rules! create_runtime {
-    ($response:ty, $transport:ty, $env:ty, $out:ty, $err:ty, $ver:expr, $init:ident) => {
-        DefaultRuntime::<$response, $transport, $env, $out, $err>::new($ver, $init);
+    ($response:ty, $transport:ty, $env:ty, $in:ty, $out:ty, $err:ty, $ver:expr, $init:expr) => {
+        DefaultRuntime::<$response, $transport, $env, $in, $out, $err>::new($ver, $init);
     };
 }
 
 /// Creates a [`crate::runtime::DefaultRuntime`] with ureq based HTTP backend and the default implementation of env-vars handling.
 #[macro_export]
 macro_rules! default_runtime {
-    ($out:ty, $err:ty, $ver:expr, $init:ident) => {
+    ($in:ty, $out:ty, $err:ty, $ver:expr, $init:expr) => {
         create_runtime!(
             UreqResponse,
             UreqTransport,
             LambdaRuntimeEnv,
+            $in,
             $out,
             $err,
             $ver,
@@ -0,0 +1,590 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::context::ArcLambdaContext;
+use crate::data::env::{RuntimeEnvVars, TRACE_ID_ENV_VAR};
+use crate::data::extensions::SharedState;
+use crate::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
+use crate::error::{
+    Error, ErrorRequest, LambdaError, HANDLER_ERR_TYPE, INIT_ERR_TYPE, MISSING_REQ_ID_ERR_TYPE,
+    SERIALIZATION_ERR_TYPE, UNMARSHAL_ERR_TYPE,
+};
+use crate::runtime::cancel::CancellationToken;
+use crate::runtime::retry::RetryPolicy;
+use crate::runtime::watchdog::{fire_at, Watchdog};
+use crate::runtime::{
+    default_exit_hook, log_api_error, log_error, ExitHook, CONTAINER_ERR_EXIT_CODE,
+    INIT_ERR_EXIT_CODE, INIT_REPORT_ERR_EXIT_CODE,
+};
+use crate::transport::AsyncTransport;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A pinned and boxed future, used to store the futures returned by user-defined closures without naming their types.
+/// It is [`Send`] so the runtime loop may run on a multi-threaded executor, e.g: using `tokio::spawn`.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The boxed form of an async event handler stored by an [`AsyncDefaultRuntime`].
+pub type AsyncEventHandler<IN, ENV, R, OUT, ERR> =
+    Box<dyn Fn(IN, ArcLambdaContext<ENV, R>) -> BoxFuture<Result<OUT, ERR>> + Send>;
+
+/// The boxed form of an async initializer stored by an [`AsyncDefaultRuntime`].
+type AsyncInitializer<IN, ENV, R, OUT, ERR> =
+    Box<dyn FnOnce() -> BoxFuture<Result<AsyncEventHandler<IN, ENV, R, OUT, ERR>, ERR>> + Send>;
+
+/// The asynchronous counterpart of [`crate::runtime::LambdaRuntime`].
+/// The HTTP Backend in use is defined by the input types `T` that implements [`AsyncTransport`] and `R` implementing [`LambdaAPIResponse`].
+/// The `OUT` type parameter is the user-defined response type which represents the success result of the event handler.
+pub trait AsyncLambdaRuntime<R, T, OUT>
+where
+    R: LambdaAPIResponse,
+    T: AsyncTransport<R>,
+    OUT: Serialize,
+{
+    /// Used to fetch the next event from the Lambda service.
+    fn next_invocation(&mut self) -> impl Future<Output = Result<R, Error>>;
+    /// Sends back a JSON formatted response to the Lambda service, after processing an event.
+    fn invocation_response(
+        &self,
+        request_id: &str,
+        response: &OUT,
+    ) -> impl Future<Output = Result<R, Error>>;
+    /// Used to report an error during initialization to the Lambda service.
+    fn initialization_error(
+        &self,
+        error_req: &ErrorRequest,
+    ) -> impl Future<Output = Result<R, Error>>;
+    /// Used to report an error during function invocation to the Lambda service.
+    fn invocation_error(
+        &self,
+        request_id: &str,
+        error_req: &ErrorRequest,
+    ) -> impl Future<Output = Result<R, Error>>;
+    /// Implements the runtime loop logic.
+    /// Resolves only if the runtime cannot continue and its exit hook did not terminate the process.
+    fn run(&mut self) -> impl Future<Output = ()>;
+}
+
+/// The default generic implementation of the [`AsyncLambdaRuntime`] interface.
+///
+/// Works like [`crate::runtime::DefaultRuntime`], except that both the initializer and the event handler are async:
+/// the initializer is a closure returning a future that resolves to the event handler,
+/// and the event handler is a closure of the form `Fn(IN, ArcLambdaContext<ENV, R>) -> impl Future<Output = Result<OUT, ERR>>`.
+///
+/// Since the future returned by the handler may hold on to the context object, the handler is passed an
+/// [`ArcLambdaContext`] which shares ownership of the env-vars and the invocation response.
+/// This requires the `ENV` type to implement [`Clone`], so the trace id can be updated while a previous context is still alive.
+///
+/// The runtime does not depend on a specific executor, although the shipped [`crate::backends::hyper`] backend requires tokio.
+/// The initializer, the event handler and the futures they return are required to be [`Send`],
+/// so the future of [`AsyncLambdaRuntime::run`] is [`Send`] as well and may be spawned on a multi-threaded executor.
+pub struct AsyncDefaultRuntime<R, T, ENV, IN, OUT, ERR>
+where
+    R: LambdaAPIResponse,
+    T: AsyncTransport<R>,
+    ENV: RuntimeEnvVars + Clone,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
+    OUT: Serialize,
+{
+    /// A shared instance of a type implementing [`crate::data::env::RuntimeEnvVars`].
+    env_vars: Arc<ENV>,
+    /// The Lambda API version string.
+    version: String,
+    /// URI of the Lambda API.
+    api_base: String,
+    /// An owned instance of the HTTP Backend implementing [`crate::transport::AsyncTransport`].
+    transport: T,
+    /// An async initialization function that sets up persistent variables and returns the event handler.
+    /// Taken when the runtime loop starts. Like the exit hook, it is only accessed through `&mut self` -
+    /// the [`Mutex`] makes the runtime [`Sync`], so the futures of its `&self` methods are [`Send`].
+    #[allow(clippy::type_complexity)]
+    initializer: Mutex<Option<AsyncInitializer<IN, ENV, R, OUT, ERR>>>,
+    /// Called with a non-zero exit code when the runtime cannot continue.
+    exit_hook: Mutex<ExitHook>,
+    /// Applied to the runtime API calls made by the runtime loop.
+    retry_policy: RetryPolicy,
+    /// The number of consecutive failed cycles of the runtime loop, used to back off before fetching the next event.
+    failures: u32,
+    /// How long before the invocation deadline the context's cancellation token is cancelled, if enabled.
+    cancellation_margin: Option<Duration>,
+    /// Whether to set the trace id of each invocation as the `_X_AMZN_TRACE_ID` env-var.
+    trace_env_var: bool,
+    /// Values shared by all invocations through the context object.
+    shared_state: Arc<SharedState>,
+}
+
+impl<R, T, ENV, IN, OUT, ERR> AsyncDefaultRuntime<R, T, ENV, IN, OUT, ERR>
+where
+    R: LambdaAPIResponse + 'static,
+    T: AsyncTransport<R>,
+    ENV: RuntimeEnvVars + Clone + 'static,
+    IN: DeserializeOwned + 'static,
+    ERR: LambdaError + 'static,
+    OUT: Serialize + 'static,
+{
+    pub fn new<I, IFUT, H, HFUT>(version: &str, initializer: I) -> Self
+    where
+        I: FnOnce() -> IFUT + Send + 'static,
+        IFUT: Future<Output = Result<H, ERR>> + Send + 'static,
+        H: Fn(IN, ArcLambdaContext<ENV, R>) -> HFUT + Send + 'static,
+        HFUT: Future<Output = Result<OUT, ERR>> + Send + 'static,
+    {
+        // Initialize default env vars and start the transport layer object
+        Self::from_parts(version, ENV::default(), T::default(), initializer)
+    }
+
+    /// Creates a runtime from existing instances of the env-vars and transport types,
+    /// e.g: to inject a scripted transport in tests.
+    ///
+    /// Panics if the runtime API address is missing from the env-vars.
+    pub fn from_parts<I, IFUT, H, HFUT>(
+        version: &str,
+        env_vars: ENV,
+        transport: T,
+        initializer: I,
+    ) -> Self
+    where
+        I: FnOnce() -> IFUT + Send + 'static,
+        IFUT: Future<Output = Result<H, ERR>> + Send + 'static,
+        H: Fn(IN, ArcLambdaContext<ENV, R>) -> HFUT + Send + 'static,
+        HFUT: Future<Output = Result<OUT, ERR>> + Send + 'static,
+    {
+        // Check for the host and port of the runtime API.
+        let api_base = match env_vars.get_runtime_api() {
+            Some(v) => v.to_string(),
+            None => panic!("Failed getting API base URL from env vars"),
+        };
+
+        // Format the version string, later used in API calls
+        let formatted_version: String = format_version_string!(version);
+
+        // Box the user-defined closures and the futures they return
+        let initializer: AsyncInitializer<IN, ENV, R, OUT, ERR> = Box::new(move || {
+            Box::pin(async move {
+                let handler = initializer().await?;
+                let boxed: AsyncEventHandler<IN, ENV, R, OUT, ERR> =
+                    Box::new(move |event, context| Box::pin(handler(event, context)));
+                Ok(boxed)
+            })
+        });
+
+        Self {
+            env_vars: Arc::new(env_vars),
+            version: formatted_version,
+            api_base,
+            transport,
+            initializer: Mutex::new(Some(initializer)),
+            exit_hook: Mutex::new(Box::new(default_exit_hook)),
+            retry_policy: RetryPolicy::default(),
+            failures: 0,
+            cancellation_margin: None,
+            trace_env_var: false,
+            shared_state: Arc::new(SharedState::default()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_env(&self) -> &ENV {
+        &self.env_vars
+    }
+
+    /// Overrides the [`ExitHook`] called when the runtime cannot continue, which defaults to [`default_exit_hook`].
+    pub fn set_exit_hook<F>(&mut self, exit_hook: F)
+    where
+        F: FnMut(i32) + Send + 'static,
+    {
+        self.exit_hook = Mutex::new(Box::new(exit_hook));
+    }
+
+    /// Overrides the [`RetryPolicy`] applied to failed runtime API calls, see [`crate::runtime::DefaultRuntime::set_retry_policy`].
+    /// Backoffs are awaited on a timer thread, so they do not block the executor.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Enables cancelling the [`CancellationToken`] passed to the event handler through the context object `margin` before the invocation deadline.
+    /// The token is cancelled by a separate thread, so its future resolves regardless of the executor in use.
+    pub fn set_cancellation_margin(&mut self, margin: Option<Duration>) {
+        self.cancellation_margin = margin;
+    }
+
+    /// Returns the [`SharedState`] passed to the event handler through the context object, e.g: to insert values before running the runtime.
+    pub fn shared_state_mut(&mut self) -> &mut SharedState {
+        Arc::make_mut(&mut self.shared_state)
+    }
+
+    /// Sets whether the trace id of each invocation is also set as the `_X_AMZN_TRACE_ID` env-var, disabled by default.
+    /// See [`crate::runtime::DefaultRuntime::set_trace_env_var`].
+    pub fn set_trace_env_var(&mut self, enabled: bool) {
+        self.trace_env_var = enabled;
+    }
+
+    /// Calls the exit hook with the given exit code.
+    fn exit(&mut self, code: i32) {
+        let exit_hook = self
+            .exit_hook
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        exit_hook(code);
+    }
+
+    /// Decides whether the runtime loop may continue after a runtime API call failed.
+    /// Errors the retry policy exits on are passed to the exit hook and return `false`,
+    /// any other error is logged and backed off according to the number of consecutive failed cycles before returning `true`.
+    async fn can_continue(&mut self, stage: &str, err: &Error) -> bool {
+        log_api_error(stage, err);
+        if self.retry_policy.should_exit(err) {
+            self.exit(CONTAINER_ERR_EXIT_CODE);
+            return false;
+        }
+        self.failures = self.failures.saturating_add(1);
+        sleep(self.retry_policy.backoff_for(self.failures)).await;
+        true
+    }
+
+    /// Reports an event that could not be deserialized into `IN` as a `Runtime.UnmarshalError`.
+    async fn unmarshal_error(&self, request_id: &str, msg: &str) -> Result<R, Error> {
+        let error_req = ErrorRequest::new(
+            UNMARSHAL_ERR_TYPE,
+            &format!("Failed deserializing event: {}", msg),
+        );
+        self.invocation_error(request_id, &error_req).await
+    }
+
+    /// Posts a serialized response of the event handler.
+    async fn post_response(&self, request_id: &str, serialized: &str) -> Result<R, Error> {
+        let url = format!(
+            "http://{}/{}/runtime/invocation/{}/response",
+            self.api_base, self.version, request_id
+        );
+        let transport = &self.transport;
+        retry(&self.retry_policy, "response", || async {
+            let resp = transport.post(&url, Some(serialized), None).await?;
+
+            handle_response!(resp);
+
+            Ok(resp)
+        })
+        .await
+    }
+
+    /// Sends an [`ErrorRequest`] as a JSON body to the given `url`, setting its error type as the error type header.
+    async fn post_error(&self, url: &str, error_req: &ErrorRequest) -> Result<R, Error> {
+        let serialized = serde_json::to_string(error_req)?;
+        let headers = Some((vec![AWS_FUNC_ERR_TYPE], vec![error_req.error_type.as_str()]));
+
+        let transport = &self.transport;
+        retry(&self.retry_policy, "error", || async {
+            let resp = transport
+                .post(url, Some(&serialized), headers.clone())
+                .await?;
+
+            handle_response!(resp);
+
+            Ok(resp)
+        })
+        .await
+    }
+}
+
+/// Calls `call` until it succeeds, fails with an error that is not retryable or all attempts were made,
+/// awaiting the backoff of `policy` between attempts. See [`RetryPolicy::retry`].
+async fn retry<R, F, FUT>(policy: &RetryPolicy, stage: &str, mut call: F) -> Result<R, Error>
+where
+    F: FnMut() -> FUT,
+    FUT: Future<Output = Result<R, Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(err) if attempt < policy.max_attempts && RetryPolicy::is_retryable(&err) => {
+                log_api_error(&format!("{}_retry", stage), &err);
+                sleep(policy.backoff_for(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Waits for `duration` without blocking the executor.
+/// The timer runs on a separate thread shared by all runtimes, since the runtime does not depend on a specific executor.
+fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        wake_at: Instant::now() + duration,
+    }
+}
+
+/// The future returned by [`sleep`], registering its waker with the timer thread until `wake_at` is reached.
+struct Sleep {
+    wake_at: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.wake_at {
+            return Poll::Ready(());
+        }
+        static TIMER: OnceLock<Sender<(Instant, Waker)>> = OnceLock::new();
+        let timer = TIMER.get_or_init(|| {
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || run_timer(receiver));
+            sender
+        });
+        // The timer thread never exits, so sending cannot fail.
+        let _ = timer.send((self.wake_at, cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+/// Wakes the wakers received from `timers` once their instant is reached, in order.
+fn run_timer(timers: Receiver<(Instant, Waker)>) {
+    let mut pending: BTreeMap<(Instant, u64), Waker> = BTreeMap::new();
+    let mut seq = 0;
+    loop {
+        let received = match pending.first_key_value() {
+            Some(((wake_at, _), _)) => {
+                timers.recv_timeout(wake_at.saturating_duration_since(Instant::now()))
+            }
+            None => timers.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok((wake_at, waker)) => {
+                pending.insert((wake_at, seq), waker);
+                seq += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        while let Some(entry) = pending.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            entry.remove().wake();
+        }
+    }
+}
+
+impl<R, T, ENV, IN, OUT, ERR> AsyncLambdaRuntime<R, T, OUT>
+    for AsyncDefaultRuntime<R, T, ENV, IN, OUT, ERR>
+where
+    R: LambdaAPIResponse + 'static,
+    T: AsyncTransport<R>,
+    ENV: RuntimeEnvVars + Clone + 'static,
+    IN: DeserializeOwned + 'static,
+    ERR: LambdaError + 'static,
+    OUT: Serialize + 'static,
+{
+    async fn run(&mut self) {
+        // Run the app's initializer and check for errors
+        let initializer = match self
+            .initializer
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            Some(init) => init,
+            // The loop has already been started once.
+            None => return,
+        };
+        let lambda = match initializer().await {
+            Err(init_err) => {
+                // Try reporting to the Lambda service if there is an error during initialization,
+                // then log the error and exit with a code describing whether reporting succeeded.
+                let error_req = ErrorRequest::from_error(&init_err, INIT_ERR_TYPE);
+                let exit_code = match self.initialization_error(&error_req).await {
+                    Ok(_) => {
+                        log_error("init", &error_req, None);
+                        INIT_ERR_EXIT_CODE
+                    }
+                    Err(err) => {
+                        log_error("init", &error_req, Some(&err));
+                        INIT_REPORT_ERR_EXIT_CODE
+                    }
+                };
+                self.exit(exit_code);
+                return;
+            }
+            // On successfull init, unwrap the underlying closure (event handler)
+            Ok(event_handler) => event_handler,
+        };
+
+        // Start event processing loop as specified in [https://docs.aws.amazon.com/lambda/latest/dg/runtimes-custom.html]
+        loop {
+            // Get the next event in the queue.
+            // Failing to get the next event will either exit (on server error) or continue (on any other error).
+            let next = match self.next_invocation().await {
+                Ok(resp) => Arc::new(resp),
+                Err(err) => match self.can_continue("next", &err).await {
+                    true => continue,
+                    false => return,
+                },
+            };
+            let request_id = match next.aws_request_id() {
+                Some(rid) => rid.to_string(),
+                None => {
+                    let error_req = ErrorRequest::new(
+                        MISSING_REQ_ID_ERR_TYPE,
+                        "Missing Lambda-Runtime-Aws-Request-Id header",
+                    );
+                    if let Err(err) = self.initialization_error(&error_req).await {
+                        if !self.can_continue("init_error", &err).await {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            // Retrieve the event JSON and deserialize it into the handler's input type
+            let event: IN = match next.event_response().map(serde_json::from_str) {
+                Some(Ok(ev)) => ev,
+                parse_result => {
+                    let msg = match parse_result {
+                        Some(Err(err)) => err.to_string(),
+                        _ => "Missing event payload".to_string(),
+                    };
+                    if let Err(err) = self.unmarshal_error(&request_id, &msg).await {
+                        if !self.can_continue("error", &err).await {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            // Create the cancellation token, which is cancelled by a watchdog thread
+            let canceller = match (self.cancellation_margin, next.deadline()) {
+                (Some(margin), Some(deadline)) => {
+                    let token = CancellationToken::new();
+                    let watchdog = Arc::new(Watchdog::default());
+                    let cancel_at = fire_at(deadline, margin);
+                    let (thread_token, thread_watchdog) = (token.clone(), Arc::clone(&watchdog));
+                    std::thread::spawn(move || {
+                        thread_watchdog.watch(cancel_at, || thread_token.cancel())
+                    });
+                    Some((token, watchdog))
+                }
+                _ => None,
+            };
+
+            // Create the context object for the lambda execution
+            let context = ArcLambdaContext {
+                env_vars: Arc::clone(&self.env_vars),
+                invo_resp: next,
+                cancellation: canceller.as_ref().map(|(token, _)| token.clone()),
+                shared_state: Arc::clone(&self.shared_state),
+            };
+
+            // Execute the event handler
+            let lambda_output = lambda(event, context).await;
+            if let Some((_, watchdog)) = canceller {
+                watchdog.finish();
+            }
+
+            // Report the result of the handler, exiting if the runtime API signals a container error.
+            // A response that fails to serialize is reported as an error.
+            let serialized =
+                lambda_output.map(|out| serde_json::to_string(&out).map_err(Error::from));
+            let (stage, report_result) = match serialized {
+                Ok(Ok(serialized)) => (
+                    "response",
+                    self.post_response(&request_id, &serialized).await,
+                ),
+                Ok(Err(err)) => {
+                    let error_req = ErrorRequest::new(
+                        SERIALIZATION_ERR_TYPE,
+                        &format!("Failed serializing response: {}", err),
+                    );
+                    (
+                        "error",
+                        self.invocation_error(&request_id, &error_req).await,
+                    )
+                }
+                Err(err) => {
+                    let error_req = ErrorRequest::from_error(&err, HANDLER_ERR_TYPE);
+                    (
+                        "error",
+                        self.invocation_error(&request_id, &error_req).await,
+                    )
+                }
+            };
+            match report_result {
+                Ok(_) => self.failures = 0,
+                Err(err) => {
+                    if !self.can_continue(stage, &err).await {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn next_invocation(&mut self) -> Result<R, Error> {
+        let url = format!(
+            "http://{}/{}/runtime/invocation/next",
+            self.api_base, self.version
+        );
+        let transport = &self.transport;
+        let resp = retry(&self.retry_policy, "next", || async {
+            let resp = transport.get(&url, None, None).await?;
+
+            handle_response!(resp);
+
+            Ok(resp)
+        })
+        .await?;
+
+        // Pass the value of the "Lambda-Runtime-Trace-Id" header on to the env-vars instance - and to the "_X_AMZN_TRACE_ID" env var if enabled,
+        // clearing the trace id of the previous invocation if AWS did not return one
+        let trace_id = resp.trace_id();
+        if self.trace_env_var {
+            match trace_id {
+                Some(trace_id) => std::env::set_var(TRACE_ID_ENV_VAR, trace_id),
+                None => std::env::remove_var(TRACE_ID_ENV_VAR),
+            }
+        }
+        Arc::make_mut(&mut self.env_vars).set_trace_id(trace_id);
+
+        Ok(resp)
+    }
+
+    async fn invocation_response(&self, request_id: &str, response: &OUT) -> Result<R, Error> {
+        let serialized = serde_json::to_string(response)?;
+        self.post_response(request_id, &serialized).await
+    }
+
+    async fn initialization_error(&self, error_req: &ErrorRequest) -> Result<R, Error> {
+        let url = format!(
+            "http://{}/{}/runtime/init/error",
+            self.api_base, self.version
+        );
+        self.post_error(&url, error_req).await
+    }
+
+    async fn invocation_error(
+        &self,
+        request_id: &str,
+        error_req: &ErrorRequest,
+    ) -> Result<R, Error> {
+        let url = format!(
+            "http://{}/{}/runtime/invocation/{}/error",
+            self.api_base, self.version, request_id
+        );
+        self.post_error(&url, error_req).await
+    }
+}
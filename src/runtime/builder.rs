@@ -0,0 +1,242 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::context::{ContextFactory, RefContextFactory};
+use crate::data::env::RuntimeEnvVars;
+use crate::data::extensions::SharedState;
+use crate::data::response::LambdaAPIResponse;
+use crate::error::{Error, LambdaError};
+use crate::runtime::handler::Handler;
+use crate::runtime::layer::{BoxedLayer, Layer};
+use crate::runtime::retry::RetryPolicy;
+use crate::runtime::{
+    default_exit_hook, json_serializer, BoxedHandler, DefaultRuntime, ExitHook, Initializer,
+    Serializer, ShutdownHandle,
+};
+use crate::transport::Transport;
+use crate::LAMBDA_VER;
+
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Configures and creates a [`DefaultRuntime`], returned by [`DefaultRuntime::builder`].
+///
+/// Any setting that is not set explicitly falls back to the same default used by [`DefaultRuntime::new`] -
+/// the env-vars and transport are created using their [`Default`] implementations,
+/// and the runtime API address is read from the `AWS_LAMBDA_RUNTIME_API` env-var.
+pub struct RuntimeBuilder<R, T, ENV, IN, OUT, ERR, CF = RefContextFactory>
+where
+    R: LambdaAPIResponse,
+    T: Transport<R>,
+    ENV: RuntimeEnvVars,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
+    OUT: Serialize,
+    CF: ContextFactory<ENV, R>,
+{
+    initializer: Initializer<IN, ENV, R, OUT, ERR, CF>,
+    context_factory: CF,
+    version: String,
+    api_base: Option<String>,
+    env_vars: Option<ENV>,
+    transport: Option<T>,
+    post_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    exit_hook: ExitHook,
+    serializer: Serializer<OUT>,
+    layers: Vec<BoxedLayer<IN, ENV, R, OUT, ERR, CF>>,
+    exit_on_panic: bool,
+    timeout_margin: Option<Duration>,
+    cancellation_margin: Option<Duration>,
+    shared_state: SharedState,
+    trace_env_var: bool,
+}
+
+impl<R, T, ENV, IN, OUT, ERR, CF> RuntimeBuilder<R, T, ENV, IN, OUT, ERR, CF>
+where
+    R: LambdaAPIResponse,
+    T: Transport<R>,
+    ENV: RuntimeEnvVars,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
+    OUT: Serialize,
+    CF: ContextFactory<ENV, R>,
+{
+    /// Creates a builder for a runtime running the given `initializer`, using [`LAMBDA_VER`] as the API version.
+    /// The initializer may be any [`FnOnce`] closure returning a [`Handler`] - e.g: an [`FnMut`] closure, which is boxed by the builder.
+    pub fn new<I, H>(initializer: I) -> Self
+    where
+        I: FnOnce() -> Result<H, ERR> + Send + 'static,
+        H: Handler<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+        CF: Default,
+    {
+        Self::with_context_factory(CF::default(), initializer)
+    }
+
+    /// Creates a builder like [`RuntimeBuilder::new`], whose runtime passes the context objects created by `context_factory` to the event handler.
+    pub fn with_context_factory<I, H>(context_factory: CF, initializer: I) -> Self
+    where
+        I: FnOnce() -> Result<H, ERR> + Send + 'static,
+        H: Handler<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+    {
+        let initializer: Initializer<IN, ENV, R, OUT, ERR, CF> = Box::new(move || {
+            initializer().map(|handler| Box::new(handler) as BoxedHandler<IN, ENV, R, OUT, ERR, CF>)
+        });
+        RuntimeBuilder {
+            initializer,
+            context_factory,
+            version: LAMBDA_VER.to_string(),
+            api_base: None,
+            env_vars: None,
+            transport: None,
+            post_timeout: None,
+            retry_policy: RetryPolicy::default(),
+            exit_hook: Box::new(default_exit_hook),
+            serializer: json_serializer::<OUT>,
+            layers: Vec::new(),
+            exit_on_panic: true,
+            timeout_margin: None,
+            cancellation_margin: None,
+            shared_state: SharedState::default(),
+            trace_env_var: false,
+        }
+    }
+
+    /// Sets the `host:port` address of the runtime API, instead of reading it from the env-vars.
+    pub fn api_endpoint(mut self, api_base: &str) -> Self {
+        self.api_base = Some(api_base.to_string());
+        self
+    }
+
+    /// Sets the Lambda API version string.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// Sets the env-vars instance used by the runtime.
+    pub fn env(mut self, env_vars: ENV) -> Self {
+        self.env_vars = Some(env_vars);
+        self
+    }
+
+    /// Sets the transport instance used by the runtime.
+    pub fn transport(mut self, transport: T) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Sets the timeout of requests reporting responses and errors, see [`Transport::set_post_timeout`].
+    /// With `UreqTransport` it also applies to streamed responses, bounding each write of the stream.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.post_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the [`RetryPolicy`] applied to failed runtime API calls.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the [`ExitHook`] called when the runtime cannot continue.
+    pub fn exit_hook<F>(mut self, exit_hook: F) -> Self
+    where
+        F: FnMut(i32) + Send + 'static,
+    {
+        self.exit_hook = Box::new(exit_hook);
+        self
+    }
+
+    /// Sets the [`Serializer`] used to serialize the responses of the event handler, which defaults to [`json_serializer`].
+    pub fn serializer(mut self, serializer: Serializer<OUT>) -> Self {
+        self.serializer = serializer;
+        self
+    }
+
+    /// Sets whether the runtime exits after the event handler panicked, see [`DefaultRuntime::set_exit_on_panic`].
+    pub fn exit_on_panic(mut self, exit_on_panic: bool) -> Self {
+        self.exit_on_panic = exit_on_panic;
+        self
+    }
+
+    /// Enables the timeout watchdog with the given `margin`, see [`DefaultRuntime::set_timeout_watchdog`].
+    pub fn timeout_watchdog(mut self, margin: Duration) -> Self {
+        self.timeout_margin = Some(margin);
+        self
+    }
+
+    /// Enables cancelling the context's cancellation token `margin` before the invocation deadline, see [`DefaultRuntime::set_cancellation_margin`].
+    pub fn cancellation_margin(mut self, margin: Duration) -> Self {
+        self.cancellation_margin = Some(margin);
+        self
+    }
+
+    /// Inserts a value into the [`SharedState`] passed to the event handler through the context object.
+    pub fn shared_state<S: Send + Sync + 'static>(mut self, value: S) -> Self {
+        self.shared_state.insert(value);
+        self
+    }
+
+    /// Sets whether the trace id of each invocation is also set as an env-var, see [`DefaultRuntime::set_trace_env_var`].
+    pub fn trace_env_var(mut self, enabled: bool) -> Self {
+        self.trace_env_var = enabled;
+        self
+    }
+
+    /// Adds a [`Layer`] wrapping the event handler, inside any previously added layers.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+    {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Creates the runtime, returns an [`Error::Env`] if the runtime API address was neither set nor found in the env-vars.
+    #[allow(clippy::type_complexity)]
+    pub fn build(self) -> Result<DefaultRuntime<R, T, ENV, IN, OUT, ERR, CF>, Error> {
+        let env_vars = self.env_vars.unwrap_or_default();
+
+        // Check for the host and port of the runtime API.
+        let api_base = match self.api_base {
+            Some(api_base) => api_base,
+            None => match env_vars.get_runtime_api() {
+                Some(v) => v.to_string(),
+                None => return Err(Error::Env("AWS_LAMBDA_RUNTIME_API is not set".to_string())),
+            },
+        };
+
+        let mut transport = self.transport.unwrap_or_default();
+        if self.post_timeout.is_some() {
+            transport.set_post_timeout(self.post_timeout);
+        }
+
+        // Format the version string, later used in API calls
+        let formatted_version: String = format_version_string!(self.version);
+
+        Ok(DefaultRuntime {
+            env_vars,
+            version: formatted_version,
+            api_base,
+            transport,
+            initializer: Some(self.initializer),
+            handler: None,
+            handler_shut_down: false,
+            layers: self.layers,
+            context_factory: self.context_factory,
+            shared_state: self.shared_state,
+            trace_env_var: self.trace_env_var,
+            exit_hook: self.exit_hook,
+            retry_policy: self.retry_policy,
+            serializer: self.serializer,
+            exit_on_panic: self.exit_on_panic,
+            timeout_margin: self.timeout_margin,
+            cancellation_margin: self.cancellation_margin,
+            shutdown: ShutdownHandle::default(),
+        })
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// A token signaling an event handler that it should stop, e.g: as the invocation deadline is approaching.
+///
+/// The token can be polled using [`CancellationToken::is_cancelled`], waited on by blocking code using [`CancellationToken::wait`]
+/// and awaited by async code using [`CancellationToken::cancelled`], which does not depend on a specific executor.
+/// Cloning the token is cheap and all clones share the same state.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Cancels the token, waking up all the threads and tasks waiting on it.
+    pub fn cancel(&self) {
+        let wakers = {
+            let mut state = self.lock();
+            state.cancelled = true;
+            std::mem::take(&mut state.wakers)
+        };
+        self.inner.changed.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Returns `true` if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    /// Blocks the current thread until the token is cancelled.
+    pub fn wait(&self) {
+        let mut state = self.lock();
+        while !state.cancelled {
+            state = match self.inner.changed.wait(state) {
+                Ok(state) => state,
+                Err(err) => err.into_inner(),
+            };
+        }
+    }
+
+    /// Blocks the current thread until the token is cancelled or `timeout` has elapsed, returns `true` if the token was cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.lock();
+        let result = self
+            .inner
+            .changed
+            .wait_timeout_while(state, timeout, |state| !state.cancelled);
+        match result {
+            Ok((state, _)) => state.cancelled,
+            Err(err) => err.into_inner().0.cancelled,
+        }
+    }
+
+    /// Returns a future that resolves once the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+/// A future returned by [`CancellationToken::cancelled`], resolving once the token is cancelled.
+pub struct Cancelled {
+    token: CancellationToken,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.token.lock();
+        if state.cancelled {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
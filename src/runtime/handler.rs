@@ -0,0 +1,57 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::context::{ContextFactory, RefContextFactory};
+use crate::data::env::RuntimeEnvVars;
+use crate::data::extensions::SharedState;
+use crate::data::response::LambdaAPIResponse;
+use crate::error::{ErrorRequest, LambdaError, HANDLER_ERR_TYPE};
+
+/// An event handler driven by a [`crate::runtime::DefaultRuntime`] through its lifecycle.
+///
+/// The type parameters correspond to the ones defined by [`crate::runtime::DefaultRuntime`],
+/// the context object passed to the handler is created by the `CF` [`ContextFactory`] - a [`crate::data::context::RefLambdaContext`] by default.
+/// Closures accepting the event and the context object implement this trait by calling the closure in [`Handler::handle`],
+/// so an initializer may return either a closure or a type implementing the trait.
+pub trait Handler<IN, ENV, R, OUT, ERR, CF = RefContextFactory>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    /// Called once after the initializer returned the handler and before processing any events, e.g: to warm up connections.
+    /// Values inserted into the runtime's [`SharedState`] - e.g: a connection pool created here - are passed to every invocation through the context object.
+    /// A failure is reported to the Lambda service as an initialization error.
+    fn init(&mut self, _shared_state: &mut SharedState) -> Result<(), ERR> {
+        Ok(())
+    }
+
+    /// Processes an event, accepting the deserialized event and the context object of the current invocation.
+    fn handle(&mut self, event: IN, context: CF::Context<'_>) -> Result<OUT, ERR>;
+
+    /// Translates an error returned by [`Handler::handle`] into the [`ErrorRequest`] reported to the Lambda service.
+    /// Defaults to [`ErrorRequest::from_error`] with [`HANDLER_ERR_TYPE`] as the default error type.
+    fn on_error(&mut self, err: &ERR) -> ErrorRequest {
+        ErrorRequest::from_error(err, HANDLER_ERR_TYPE)
+    }
+
+    /// Called once the runtime stops processing events, e.g: to flush buffered data or close connections.
+    /// See [`crate::runtime::DefaultRuntime::shutdown_handler`].
+    fn shutdown(&mut self) {}
+}
+
+impl<F, IN, ENV, R, OUT, ERR, CF> Handler<IN, ENV, R, OUT, ERR, CF> for F
+where
+    F: for<'a> FnMut(IN, CF::Context<'a>) -> Result<OUT, ERR>,
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    #[inline(always)]
+    fn handle(&mut self, event: IN, context: CF::Context<'_>) -> Result<OUT, ERR> {
+        self(event, context)
+    }
+}
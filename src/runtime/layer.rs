@@ -0,0 +1,270 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::context::{ContextFactory, LambdaContext, RefContextFactory};
+use crate::data::env::RuntimeEnvVars;
+use crate::data::response::LambdaAPIResponse;
+use crate::error::LambdaError;
+use crate::runtime::handler::Handler;
+use crate::runtime::panic::panic_message;
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+/// A middleware wrapping the call to the event handler of a [`crate::runtime::DefaultRuntime`].
+///
+/// Each layer receives the event and the context object of the invocation - created by the `CF` [`ContextFactory`] - along with the [`Next`] layer in the stack,
+/// it may call [`Next::run`] to continue down the stack and inspect or transform its result -
+/// or return early without calling it, short-circuiting the stack and the event handler.
+///
+/// Layers are called in the order they were added to the runtime, so the first layer added is the outermost one.
+pub trait Layer<IN, ENV, R, OUT, ERR, CF = RefContextFactory>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    /// Handles an event, usually by passing it on to [`Next::run`] along with the context object.
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR>;
+}
+
+/// A boxed [`Layer`] added to a [`crate::runtime::DefaultRuntime`].
+pub type BoxedLayer<IN, ENV, R, OUT, ERR, CF = RefContextFactory> =
+    Box<dyn Layer<IN, ENV, R, OUT, ERR, CF> + Send>;
+
+/// The rest of the layer stack below the current [`Layer`], ending with the event handler.
+pub struct Next<'n, IN, ENV, R, OUT, ERR, CF = RefContextFactory>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    layers: &'n mut [BoxedLayer<IN, ENV, R, OUT, ERR, CF>],
+    handler: &'n mut dyn Handler<IN, ENV, R, OUT, ERR, CF>,
+}
+
+impl<'n, IN, ENV, R, OUT, ERR, CF> Next<'n, IN, ENV, R, OUT, ERR, CF>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    /// Creates a stack calling the given `layers` in order, and then the `handler`.
+    pub fn new(
+        layers: &'n mut [BoxedLayer<IN, ENV, R, OUT, ERR, CF>],
+        handler: &'n mut dyn Handler<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Self {
+        Next { layers, handler }
+    }
+
+    /// Calls the next layer in the stack, or the event handler if there are no more layers.
+    pub fn run(self, event: IN, context: CF::Context<'_>) -> Result<OUT, ERR> {
+        match self.layers.split_first_mut() {
+            Some((layer, layers)) => layer.call(
+                event,
+                context,
+                Next {
+                    layers,
+                    handler: self.handler,
+                },
+            ),
+            None => self.handler.handle(event, context),
+        }
+    }
+}
+
+/// Writes a single line JSON log record about an invocation to stderr.
+fn log_invocation(level: &str, request_id: Option<&str>, fields: serde_json::Value) {
+    let mut record = serde_json::json!({
+        "level": level,
+        "stage": "handler",
+        "requestId": request_id,
+    });
+    if let (Some(record), serde_json::Value::Object(fields)) = (record.as_object_mut(), fields) {
+        record.extend(fields);
+    }
+    eprintln!("{}", record);
+}
+
+/// A function receiving the request id of an invocation and the time it took to handle it.
+pub type TimingReporter = fn(Option<&str>, Duration);
+
+/// The default [`TimingReporter`], logs the duration of the invocation as a JSON record to stderr.
+pub fn log_timing(request_id: Option<&str>, elapsed: Duration) {
+    log_invocation(
+        "INFO",
+        request_id,
+        serde_json::json!({ "durationMs": elapsed.as_secs_f64() * 1000.0 }),
+    );
+}
+
+/// A [`Layer`] measuring the time it takes the rest of the stack to handle each event.
+#[derive(Clone, Copy, Debug)]
+pub struct TimingLayer {
+    report: TimingReporter,
+}
+
+impl TimingLayer {
+    /// Creates a layer passing the measured durations to `report`.
+    pub fn new(report: TimingReporter) -> Self {
+        TimingLayer { report }
+    }
+}
+
+impl Default for TimingLayer {
+    /// Creates a layer logging the measured durations using [`log_timing`].
+    fn default() -> Self {
+        Self::new(log_timing)
+    }
+}
+
+impl<IN, ENV, R, OUT, ERR, CF> Layer<IN, ENV, R, OUT, ERR, CF> for TimingLayer
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR> {
+        let request_id = context.aws_request_id().map(str::to_string);
+        let start = Instant::now();
+        let result = next.run(event, context);
+        (self.report)(request_id.as_deref(), start.elapsed());
+        result
+    }
+}
+
+/// A [`Layer`] logging the start of each invocation and its result as JSON records to stderr.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoggingLayer;
+
+impl<IN, ENV, R, OUT, ERR, CF> Layer<IN, ENV, R, OUT, ERR, CF> for LoggingLayer
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR> {
+        let request_id = context.aws_request_id().map(str::to_string);
+        let request_id = request_id.as_deref();
+        log_invocation(
+            "INFO",
+            request_id,
+            serde_json::json!({ "message": "Invocation started" }),
+        );
+        let result = next.run(event, context);
+        match &result {
+            Ok(_) => log_invocation(
+                "INFO",
+                request_id,
+                serde_json::json!({ "message": "Invocation succeeded" }),
+            ),
+            Err(err) => log_invocation(
+                "ERROR",
+                request_id,
+                serde_json::json!({
+                    "message": "Invocation failed",
+                    "errorType": err.error_type(),
+                    "errorMessage": err.error_message(),
+                }),
+            ),
+        }
+        result
+    }
+}
+
+/// A [`Layer`] catching panics raised by the rest of the stack and converting their message into an error of type `ERR`.
+///
+/// The panic is still printed to stderr by the panic hook.
+pub struct CatchPanicLayer<ERR> {
+    to_error: fn(String) -> ERR,
+}
+
+impl<ERR> CatchPanicLayer<ERR> {
+    /// Creates a layer converting the panic message into an error using `to_error`.
+    pub fn new(to_error: fn(String) -> ERR) -> Self {
+        CatchPanicLayer { to_error }
+    }
+}
+
+impl<ERR: From<String>> Default for CatchPanicLayer<ERR> {
+    /// Creates a layer converting the panic message into an error using [`From<String>`].
+    fn default() -> Self {
+        Self::new(ERR::from)
+    }
+}
+
+impl<IN, ENV, R, OUT, ERR, CF> Layer<IN, ENV, R, OUT, ERR, CF> for CatchPanicLayer<ERR>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR> {
+        // The handler's state may be left inconsistent by the panic, which is up to the handler to tolerate
+        match catch_unwind(AssertUnwindSafe(|| next.run(event, context))) {
+            Ok(result) => result,
+            Err(payload) => Err((self.to_error)(format!(
+                "Handler panicked: {}",
+                panic_message(payload.as_ref())
+            ))),
+        }
+    }
+}
+
+/// A [`Layer`] validating each event before passing it down the stack,
+/// short-circuiting with the error returned by the validator for invalid events.
+pub struct ValidateLayer<IN, ERR> {
+    validate: fn(&IN) -> Result<(), ERR>,
+}
+
+impl<IN, ERR> ValidateLayer<IN, ERR> {
+    /// Creates a layer validating events using `validate`.
+    pub fn new(validate: fn(&IN) -> Result<(), ERR>) -> Self {
+        ValidateLayer { validate }
+    }
+}
+
+impl<IN, ENV, R, OUT, ERR, CF> Layer<IN, ENV, R, OUT, ERR, CF> for ValidateLayer<IN, ERR>
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR> {
+        (self.validate)(&event)?;
+        next.run(event, context)
+    }
+}
@@ -2,31 +2,51 @@
 
 // `SPDX-License-Identifier: MIT OR Apache-2.0`
 
-use crate::data::context::RefLambdaContext;
-use crate::data::env::RuntimeEnvVars;
-use crate::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
-use crate::error::{Error, CONTAINER_ERR};
+use crate::data::context::{ContextFactory, RefContextFactory, RefLambdaContext};
+use crate::data::env::{RuntimeEnvVars, TRACE_ID_ENV_VAR};
+use crate::data::extensions::{Extensions, SharedState};
+use crate::data::response::{
+    LambdaAPIResponse, AWS_FUNC_ERR_BODY, AWS_FUNC_ERR_TYPE, AWS_FUNC_RESPONSE_MODE,
+};
+use crate::error::{
+    Error, ErrorRequest, LambdaError, HANDLER_ERR_TYPE, INIT_ERR_TYPE, MISSING_REQ_ID_ERR_TYPE,
+    SERIALIZATION_ERR_TYPE, UNMARSHAL_ERR_TYPE,
+};
+use crate::runtime::builder::RuntimeBuilder;
+use crate::runtime::cancel::CancellationToken;
+use crate::runtime::handler::Handler;
+use crate::runtime::layer::{BoxedLayer, Layer, Next};
+use crate::runtime::panic::catch_panic;
+use crate::runtime::retry::RetryPolicy;
+use crate::runtime::stream::ResponseStream;
+use crate::runtime::watchdog::{fire_at, Watchdog};
 use crate::transport::Transport;
 
-use std::env::set_var;
-use std::ffi::OsStr;
-use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-// Already handles any panic inducing errors
+// Converts error status codes into the matching `Error` variant, leaving the decision whether to exit to the caller.
 macro_rules! handle_response {
     ($resp:expr) => {
         let status_code = $resp.get_status_code();
         match status_code {
             400..=499 => {
-                let err = $resp.error_response().or(Some("")).unwrap();
-                return Err(Error::new(format!(
-                    "Client error ({}). ErrorResponse: {}",
-                    status_code, err
-                )));
+                return Err(Error::Http {
+                    status: status_code,
+                    body: $resp
+                        .error_response()
+                        .and_then(|body| serde_json::from_str(body).ok()),
+                });
+            }
+            500..=599 => {
+                return Err(Error::Container {
+                    status: status_code,
+                })
             }
-            500 => panic!("{}", CONTAINER_ERR),
             _ => (),
         };
     };
@@ -42,6 +62,38 @@ macro_rules! format_version_string {
     };
 }
 
+/// Defines the [`crate::runtime::cancel::CancellationToken`] signaling event handlers that the invocation deadline is approaching.
+pub mod cancel;
+
+/// Defines the [`crate::runtime::stream::ResponseStream`] used to stream responses back to the Lambda service.
+pub mod stream;
+
+/// Defines the [`crate::runtime::builder::RuntimeBuilder`] used to configure a [`DefaultRuntime`].
+pub mod builder;
+
+/// Defines the [`crate::runtime::handler::Handler`] trait driven by a [`DefaultRuntime`].
+pub mod handler;
+
+/// Defines the [`crate::runtime::layer::Layer`] middleware wrapping the event handler, and the standard layers.
+pub mod layer;
+
+/// Catches panics raised by the event handler, recording their location and backtrace.
+mod panic;
+
+/// Reports invocations that are about to time out.
+mod watchdog;
+pub use watchdog::TIMEOUT_ERR_TYPE;
+
+/// Defines the [`crate::runtime::retry::RetryPolicy`] applied to failed runtime API calls.
+pub mod retry;
+
+/// Defines the [`crate::runtime::xray::XRayLayer`] sending X-Ray subsegments timing the event handler and its outbound calls.
+pub mod xray;
+
+/// An asynchronous runtime implementation for event handlers returning futures.
+#[cfg(feature = "async")]
+pub mod async_runtime;
+
 /// A generic trait defining an interface for a Lambda runtime.
 /// The HTTP Backend in use is defined by the input types `T` that implements [`Transport`] and `R` implementing [`LambdaAPIResponse`].
 /// The `OUT` type parameter is the user-defined response type which represents the success result of the event handler.
@@ -58,44 +110,214 @@ where
     /// Sends back a JSON formatted response to the Lambda service, after processing an event.
     fn invocation_response(&self, request_id: &str, response: &OUT) -> Result<R, Error>;
     /// Used to report an error during initialization to the Lambda service.
-    fn initialization_error(
-        &self,
-        error_type: Option<&str>,
-        error_req: Option<&str>,
-    ) -> Result<R, Error>;
+    fn initialization_error(&self, error_req: &ErrorRequest) -> Result<R, Error>;
     /// Used to report an error during function invocation to the Lambda service.
-    fn invocation_error(
-        &self,
-        request_id: &str,
-        error_type: Option<&str>,
-        error_req: Option<&str>,
-    ) -> Result<R, Error>;
+    fn invocation_error(&self, request_id: &str, error_req: &ErrorRequest) -> Result<R, Error>;
     /// Implements the runtime loop logic.
+    /// Returns when a shutdown was requested, or if the runtime cannot continue and its exit hook did not terminate the process.
     fn run(&mut self);
+    /// Runs a single next/handle/respond cycle, initializing the runtime first if it wasn't already.
+    /// Failed runtime API calls are logged and returned, leaving the decision whether to continue to the caller.
+    fn run_once(&mut self) -> Result<InvocationOutcome, Error>;
+    /// Runs invocation cycles until `predicate` returns `true` for an outcome, a shutdown is requested or a cycle fails.
+    /// Unlike [`LambdaRuntime::run`], errors are returned to the caller instead of being passed to an exit hook.
+    fn run_until<P>(&mut self, predicate: P) -> Result<(), Error>
+    where
+        P: FnMut(&InvocationOutcome) -> bool;
+    /// Runs up to `n` invocation cycles and returns their outcomes, stopping early if a shutdown is requested or a cycle fails.
+    fn run_n(&mut self, n: usize) -> Result<Vec<InvocationOutcome>, Error>;
+}
+
+/// The result of a single invocation cycle run by [`LambdaRuntime::run_once`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvocationOutcome {
+    /// The handler succeeded and its response was sent to the Lambda service.
+    Response { request_id: String },
+    /// The handler streamed its response, `error_type` is set if it failed after starting the stream.
+    Streamed {
+        request_id: String,
+        error_type: Option<String>,
+    },
+    /// The handler failed and its error was reported to the Lambda service.
+    HandlerError {
+        request_id: String,
+        error_type: String,
+    },
+    /// The event could not be deserialized into the handler's input type and was reported as a `Runtime.UnmarshalError`.
+    UnmarshalError { request_id: String },
+    /// The invocation was missing its request id and was reported as an initialization error.
+    MissingRequestId,
+    /// The response of the handler could not be serialized and was reported as a `Runtime.SerializationError`.
+    SerializationError { request_id: String },
+    /// The handler panicked and the panic was reported as a `Runtime.Panic` error.
+    Panicked { request_id: String, message: String },
+    /// The handler was about to exceed the invocation deadline and a `Runtime.Timeout` error was reported by the watchdog -
+    /// or sent in the trailers of the response stream if it was started, the result of the handler was discarded.
+    TimedOut { request_id: String },
+}
+
+impl InvocationOutcome {
+    /// Returns the request id of the invocation, if it had one.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            InvocationOutcome::Response { request_id }
+            | InvocationOutcome::Streamed { request_id, .. }
+            | InvocationOutcome::HandlerError { request_id, .. }
+            | InvocationOutcome::UnmarshalError { request_id }
+            | InvocationOutcome::SerializationError { request_id }
+            | InvocationOutcome::Panicked { request_id, .. }
+            | InvocationOutcome::TimedOut { request_id } => Some(request_id),
+            InvocationOutcome::MissingRequestId => None,
+        }
+    }
+}
+
+/// A cloneable handle used to stop a runtime loop gracefully, e.g: from another thread or a signal handler.
+///
+/// The flag is checked before fetching the next event, so an invocation in progress is completed and reported first.
+/// Note that a loop blocked waiting for the next event only stops after that event was processed.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle(Arc<AtomicBool>);
+
+impl ShutdownHandle {
+    /// Requests the runtime loop to stop.
+    pub fn shutdown(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if a shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The exit code passed to the [`ExitHook`] when the initializer fails and the error was reported to the Lambda service.
+pub static INIT_ERR_EXIT_CODE: i32 = 1;
+
+/// The exit code passed to the [`ExitHook`] when the initializer fails and reporting the error to the Lambda service failed as well.
+pub static INIT_REPORT_ERR_EXIT_CODE: i32 = 2;
+
+/// The exit code passed to the [`ExitHook`] when the runtime API signals a non-recoverable container error.
+pub static CONTAINER_ERR_EXIT_CODE: i32 = 3;
+
+/// The exit code passed to the [`ExitHook`] after the event handler panicked, unless the runtime is configured to keep running.
+pub static PANIC_EXIT_CODE: i32 = 4;
+
+/// A hook called by a [`DefaultRuntime`] with a non-zero exit code when the runtime cannot continue (e.g: after an initialization error).
+/// The default hook terminates the process using [`std::process::exit`],
+/// custom hooks that return cause [`LambdaRuntime::run`] to return instead.
+/// Hooks are closures, so they may capture state, e.g: to record the exit code in tests.
+/// They are required to be [`Send`] so the runtime holding them may be moved to another thread.
+pub type ExitHook = Box<dyn FnMut(i32) + Send>;
+
+/// The default [`ExitHook`], terminates the process with the given exit code.
+pub fn default_exit_hook(code: i32) {
+    std::process::exit(code)
+}
+
+/// Writes a single line JSON log record describing an [`ErrorRequest`] to stderr,
+/// along with the error that occurred while reporting it to the Lambda service, if any.
+pub(crate) fn log_error(stage: &str, error_req: &ErrorRequest, report_err: Option<&Error>) {
+    let record = serde_json::json!({
+        "level": "ERROR",
+        "stage": stage,
+        "errorType": &error_req.error_type,
+        "errorMessage": &error_req.error_message,
+        "stackTrace": &error_req.stack_trace,
+        "reportError": report_err.map(|err| err.to_string()),
+    });
+    eprintln!("{}", record);
+}
+
+/// Writes a single line JSON log record describing a failed runtime API call to stderr.
+pub(crate) fn log_api_error(stage: &str, err: &Error) {
+    let record = serde_json::json!({
+        "level": "ERROR",
+        "stage": stage,
+        "errorMessage": err.to_string(),
+    });
+    eprintln!("{}", record);
+}
+
+/// Sends an [`ErrorRequest`] serialized as `body` to the given `url`, setting `error_type` as the error type header.
+fn send_error<R, T>(transport: &T, url: &str, body: &str, error_type: &str) -> Result<R, Error>
+where
+    R: LambdaAPIResponse,
+    T: Transport<R>,
+{
+    let headers = Some((vec![AWS_FUNC_ERR_TYPE], vec![error_type]));
+    let resp = transport.post(url, Some(body), headers)?;
+
+    handle_response!(resp);
+
+    Ok(resp)
+}
+
+/// Logs a failed runtime API call and passes the error on, for use with [`Result::map_err`].
+fn logged(stage: &str) -> impl FnOnce(Error) -> Error + '_ {
+    move |err| {
+        log_api_error(stage, &err);
+        err
+    }
+}
+
+/// A function serializing the response of the event handler into the body sent to the Lambda service.
+/// Responses that fail to serialize are reported to the Lambda service as a `Runtime.SerializationError`.
+pub type Serializer<OUT> = fn(&OUT) -> Result<String, Error>;
+
+/// The default [`Serializer`], serializes the response as JSON using [`serde_json`].
+pub fn json_serializer<OUT: Serialize>(response: &OUT) -> Result<String, Error> {
+    Ok(serde_json::to_string(response)?)
 }
 
+/// A boxed closure that may be returned by the initializer of a [`DefaultRuntime`] as its event handler.
+/// Accepts the deserialized event and the context object of the current invocation,
+/// and may mutate its captured state across invocations.
+pub type EventHandler<IN, ENV, R, OUT, ERR> =
+    Box<dyn FnMut(IN, RefLambdaContext<ENV, R>) -> Result<OUT, ERR> + Send>;
+
+/// The boxed [`Handler`] driven by a [`DefaultRuntime`].
+pub type BoxedHandler<IN, ENV, R, OUT, ERR, CF = RefContextFactory> =
+    Box<dyn Handler<IN, ENV, R, OUT, ERR, CF> + Send>;
+
+/// An initialization closure run once by a [`DefaultRuntime`] before processing any events.
+pub type Initializer<IN, ENV, R, OUT, ERR, CF = RefContextFactory> =
+    Box<dyn FnOnce() -> Result<BoxedHandler<IN, ENV, R, OUT, ERR, CF>, ERR> + Send>;
+
 /// The default generic implementation of the [`LambdaRuntime`] interface.
-/// Works by accepting a pointer to an initialization function or a closure `initializer` -
+/// Works by accepting an initialization function or a closure `initializer` -
 /// that is run once and initializes "global" variables that are created once
 /// and persist across the runtime's life (DB connections, heap allocated static data etc...).
+/// The initializer may capture configuration created beforehand, e.g: in `main`.
 ///
 /// The initialization function returns a user-defined closure object that acts as the event handler and can
-/// take ownership over those variables by move.
+/// take ownership over those variables by move, the handler is [`FnMut`] so it may also mutate them (e.g: caches).
+/// Alternatively, it may return a type implementing [`Handler`] to hook into the rest of the handler's lifecycle.
 /// The Ok output type of the closure - `OUT` - should implement [`serde::Serialize`].
 ///
 /// The `R`, `T` and `OUT` type parameters correspond to the ones defined in [`LambdaRuntime`].
+/// The transport is required to be [`Sync`] as it is shared with the timeout watchdog's thread, see [`DefaultRuntime::set_timeout_watchdog`].
+/// The initializer, event handler, layers and exit hook are required to be [`Send`], so a runtime may be created on one thread and run on another.
 ///
 /// The `ENV` type parameter defines the implementation of [`crate::data::env::RuntimeEnvVars`] for reading the env-vars set for the runtime.
 ///
+/// The `IN` type parameter is the user-defined event type, which is deserialized from the event JSON before calling the handler.
+/// Events that fail to deserialize are reported back to the Lambda service as a `Runtime.UnmarshalError`.
+///
 /// The `ERR` type parameter is a user-defined type representing any error that may occur during initialization or invocation of the event handler.
-pub struct DefaultRuntime<R, T, ENV, OUT, ERR>
+/// It implements [`crate::error::LambdaError`] which defines the error type, message and stack trace reported to the Lambda service.
+///
+/// The `CF` type parameter is the [`ContextFactory`] creating the context object passed to the event handler,
+/// which defaults to [`RefContextFactory`] - passing a [`RefLambdaContext`]. See [`RuntimeBuilder::with_context_factory`].
+pub struct DefaultRuntime<R, T, ENV, IN, OUT, ERR, CF = RefContextFactory>
 where
     R: LambdaAPIResponse,
     T: Transport<R>,
     ENV: RuntimeEnvVars,
-    //   I: LambdaContext,
-    ERR: Display,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
     OUT: Serialize,
+    CF: ContextFactory<ENV, R>,
 {
     /// An owned instance of a type implementing [`crate::data::env::RuntimeEnvVars`].
     env_vars: ENV,
@@ -105,129 +327,546 @@ where
     api_base: String,
     /// An owned instance of the HTTP Backend implementing [`crate::transport::Transport`].
     transport: T,
-    /// An initialization function that sets up persistent variables and returns the event handler.
-    initializer:
-        fn()
-            -> Result<Box<dyn Fn(Option<&str>, RefLambdaContext<ENV, R>) -> Result<OUT, ERR>>, ERR>,
+    /// An initialization function that sets up persistent variables and returns the event handler, taken when it is run.
+    initializer: Option<Initializer<IN, ENV, R, OUT, ERR, CF>>,
+    /// The event handler returned by the initializer, set on the first invocation cycle.
+    handler: Option<BoxedHandler<IN, ENV, R, OUT, ERR, CF>>,
+    /// Whether the event handler was shut down, after which no more events are processed.
+    handler_shut_down: bool,
+    /// The middleware stack wrapping each call to the event handler, outermost first.
+    layers: Vec<BoxedLayer<IN, ENV, R, OUT, ERR, CF>>,
+    /// Creates the context object passed to the event handler for each invocation.
+    context_factory: CF,
+    /// Values shared by all invocations through the context object.
+    shared_state: SharedState,
+    /// Whether to set the trace id of each invocation as the `_X_AMZN_TRACE_ID` env-var.
+    trace_env_var: bool,
+    /// Called with a non-zero exit code when the runtime cannot continue.
+    exit_hook: ExitHook,
+    /// Applied to the runtime API calls made by the runtime loop.
+    retry_policy: RetryPolicy,
+    /// Serializes the response of the event handler.
+    serializer: Serializer<OUT>,
+    /// Whether to exit through the exit hook after the event handler panicked.
+    exit_on_panic: bool,
+    /// How long before the invocation deadline the watchdog reports a timeout, if enabled.
+    timeout_margin: Option<Duration>,
+    /// How long before the invocation deadline the context's cancellation token is cancelled, if enabled.
+    cancellation_margin: Option<Duration>,
+    /// Checked before fetching each event to stop the runtime loop.
+    shutdown: ShutdownHandle,
 }
 
-impl<R, T, ENV, OUT, ERR> DefaultRuntime<R, T, ENV, OUT, ERR>
+impl<R, T, ENV, IN, OUT, ERR, CF> DefaultRuntime<R, T, ENV, IN, OUT, ERR, CF>
 where
     R: LambdaAPIResponse,
-    T: Transport<R>,
+    T: Transport<R> + Sync,
     ENV: RuntimeEnvVars,
-    //   I: LambdaContext,
-    ERR: Display,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
     OUT: Serialize,
+    CF: ContextFactory<ENV, R>,
 {
-    pub fn new(
-        version: &str,
-        initializer: fn() -> Result<
-            Box<dyn Fn(Option<&str>, RefLambdaContext<ENV, R>) -> Result<OUT, ERR>>,
-            ERR,
-        >,
-    ) -> Self {
-        // Initialize default env vars and check for the host and port of the runtime API.
-        let env_vars = ENV::default();
-        let api_base = match env_vars.get_runtime_api() {
-            Some(v) => v.to_string(),
-            None => panic!("Failed getting API base URL from env vars"),
-        };
+    pub fn new<I, H>(version: &str, initializer: I) -> Self
+    where
+        I: FnOnce() -> Result<H, ERR> + Send + 'static,
+        H: Handler<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+        CF: Default,
+    {
+        // Initialize default env vars and start the transport layer object
+        Self::from_parts(version, ENV::default(), T::default(), initializer)
+    }
 
-        // Format the version string, later used in API calls
-        let formatted_version: String = format_version_string!(version);
+    /// Creates a runtime from existing instances of the env-vars and transport types,
+    /// e.g: to inject a scripted transport in tests.
+    ///
+    /// Panics if the runtime API address is missing from the env-vars, use [`DefaultRuntime::builder`] to handle this error instead.
+    pub fn from_parts<I, H>(version: &str, env_vars: ENV, transport: T, initializer: I) -> Self
+    where
+        I: FnOnce() -> Result<H, ERR> + Send + 'static,
+        H: Handler<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+        CF: Default,
+    {
+        match Self::builder(initializer)
+            .version(version)
+            .env(env_vars)
+            .transport(transport)
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(err) => panic!("Failed getting API base URL from env vars. {}", err),
+        }
+    }
 
-        // Start the transport layer object
-        let transport = T::default();
+    /// Returns a [`RuntimeBuilder`] for configuring a runtime running the given `initializer`,
+    /// using the [`Default`] instance of the context factory.
+    pub fn builder<I, H>(initializer: I) -> RuntimeBuilder<R, T, ENV, IN, OUT, ERR, CF>
+    where
+        I: FnOnce() -> Result<H, ERR> + Send + 'static,
+        H: Handler<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+        CF: Default,
+    {
+        RuntimeBuilder::new(initializer)
+    }
 
-        Self {
-            env_vars,
-            version: formatted_version,
-            api_base,
-            transport,
-            initializer,
-        }
+    /// Overrides the [`ExitHook`] called when the runtime cannot continue, which defaults to [`default_exit_hook`].
+    pub fn set_exit_hook<F>(&mut self, exit_hook: F)
+    where
+        F: FnMut(i32) + Send + 'static,
+    {
+        self.exit_hook = Box::new(exit_hook);
+    }
+
+    /// Overrides the [`RetryPolicy`] applied to failed runtime API calls, which defaults to [`RetryPolicy::default`].
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Sets whether [`LambdaRuntime::run`] exits through the exit hook with [`PANIC_EXIT_CODE`] after the event handler panicked,
+    /// which is the default. Keeping the runtime running avoids a cold start, but leaves it to the handler to tolerate
+    /// any inconsistent state left behind by the panic.
+    pub fn set_exit_on_panic(&mut self, exit_on_panic: bool) {
+        self.exit_on_panic = exit_on_panic;
+    }
+
+    /// Enables a watchdog reporting a `Runtime.Timeout` error `margin` before the deadline of invocations still being handled,
+    /// including the stage the handler was in and the elapsed time. Disabled by default.
+    ///
+    /// Once the watchdog fires, the result of the handler is discarded when it eventually returns.
+    /// If the handler has already started streaming its response, the timeout is sent in the trailers of the stream instead,
+    /// once the handler returns.
+    pub fn set_timeout_watchdog(&mut self, margin: Option<Duration>) {
+        self.timeout_margin = margin;
+    }
+
+    /// Enables cancelling the [`CancellationToken`] passed to the event handler through the context object `margin` before the invocation deadline,
+    /// letting long running handlers stop cleanly. Disabled by default, in which case the context has no token.
+    pub fn set_cancellation_margin(&mut self, margin: Option<Duration>) {
+        self.cancellation_margin = margin;
+    }
+
+    /// Sets whether the trace id of each invocation is also set as the `_X_AMZN_TRACE_ID` env-var, for libraries that read it from there.
+    /// Disabled by default, as mutating the environment is unsound while other threads may read it -
+    /// the trace id is available through [`crate::data::context::LambdaContext::trace_id`] and [`RuntimeEnvVars::get_trace_id`] instead.
+    pub fn set_trace_env_var(&mut self, enabled: bool) {
+        self.trace_env_var = enabled;
+    }
+
+    /// Adds a [`Layer`] wrapping the event handler, inside any previously added layers.
+    pub fn add_layer<L>(&mut self, layer: L)
+    where
+        L: Layer<IN, ENV, R, OUT, ERR, CF> + Send + 'static,
+    {
+        self.layers.push(Box::new(layer));
+    }
+
+    /// Returns the [`SharedState`] passed to the event handler through the context object, e.g: to insert values before running the runtime.
+    pub fn shared_state_mut(&mut self) -> &mut SharedState {
+        &mut self.shared_state
+    }
+
+    /// Returns a [`ShutdownHandle`] that stops the runtime loop of this runtime.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
     }
 
     #[inline(always)]
     pub fn get_env(&self) -> &ENV {
         &self.env_vars
     }
+
+    /// Calls [`Handler::shutdown`] if the event handler was created, and drops it.
+    /// [`LambdaRuntime::run`] calls it before returning or exiting through the exit hook,
+    /// embedders driving the loop using [`LambdaRuntime::run_once`], [`LambdaRuntime::run_until`] or [`LambdaRuntime::run_n`]
+    /// should call it once they are done processing events.
+    ///
+    /// Unlike a [`ShutdownHandle`], this does not stop a running loop - it ends the runtime's handler lifecycle,
+    /// so [`Handler::shutdown`] runs at most once and any later invocation cycle fails with an [`Error::Handler`].
+    pub fn shutdown_handler(&mut self) {
+        self.handler_shut_down = true;
+        if let Some(mut handler) = self.handler.take() {
+            handler.shutdown();
+        }
+    }
+
+    /// Runs the app's initializer and [`Handler::init`] unless the event handler was already created.
+    /// A failed initialization is reported to the Lambda service and logged, then returned as an [`Error::Handler`] -
+    /// or as the error that occurred while reporting it. Since the initializer is consumed, later calls fail as well.
+    /// Fails without reporting once the handler was shut down, see [`DefaultRuntime::shutdown_handler`].
+    fn initialize(&mut self) -> Result<(), Error> {
+        if self.handler_shut_down {
+            return Err(Error::Handler(ErrorRequest::new(
+                HANDLER_ERR_TYPE,
+                "The event handler has already shut down",
+            )));
+        }
+        if self.handler.is_some() {
+            return Ok(());
+        }
+        let initializer = match self.initializer.take() {
+            Some(initializer) => initializer,
+            None => {
+                return Err(Error::Handler(ErrorRequest::new(
+                    INIT_ERR_TYPE,
+                    "The initializer has already failed",
+                )))
+            }
+        };
+        let shared_state = &mut self.shared_state;
+        let init_result = initializer().and_then(|mut event_handler| {
+            event_handler.init(shared_state)?;
+            Ok(event_handler)
+        });
+        match init_result {
+            Ok(event_handler) => {
+                self.handler = Some(event_handler);
+                Ok(())
+            }
+            Err(init_err) => {
+                let error_req = ErrorRequest::from_error(&init_err, INIT_ERR_TYPE);
+                match self.initialization_error(&error_req) {
+                    Ok(_) => {
+                        log_error("init", &error_req, None);
+                        Err(Error::Handler(error_req))
+                    }
+                    Err(err) => {
+                        log_error("init", &error_req, Some(&err));
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports an event that could not be deserialized into `IN` as a `Runtime.UnmarshalError`.
+    fn unmarshal_error(&self, request_id: &str, msg: &str) -> Result<R, Error> {
+        let error_req = ErrorRequest::new(
+            UNMARSHAL_ERR_TYPE,
+            &format!("Failed deserializing event: {}", msg),
+        );
+        self.invocation_error(request_id, &error_req)
+    }
+
+    /// Posts a serialized response of the event handler.
+    fn post_response(&self, request_id: &str, serialized: &str) -> Result<R, Error> {
+        let url = format!(
+            "http://{}/{}/runtime/invocation/{}/response",
+            self.api_base, self.version, request_id
+        );
+        self.retry("response", || {
+            let resp = self.transport.post(&url, Some(serialized), None)?;
+
+            handle_response!(resp);
+
+            Ok(resp)
+        })
+    }
+
+    /// Ends a started response stream, sending the optional `error_req` in its trailers.
+    fn end_stream(
+        &self,
+        stream: ResponseStream<R>,
+        error_req: Option<&ErrorRequest>,
+    ) -> Result<R, Error> {
+        let resp = stream.finish(error_req)?;
+
+        handle_response!(resp);
+
+        Ok(resp)
+    }
+
+    /// Sends an [`ErrorRequest`] as a JSON body to the given `url`, setting its error type as the error type header.
+    fn post_error(&self, url: &str, error_req: &ErrorRequest) -> Result<R, Error> {
+        let serialized = serde_json::to_string(error_req)?;
+
+        self.retry("error", || {
+            send_error(&self.transport, url, &serialized, &error_req.error_type)
+        })
+    }
+
+    /// Makes a runtime API call according to the retry policy, logging each failed attempt that is retried.
+    fn retry<F>(&self, stage: &str, call: F) -> Result<R, Error>
+    where
+        F: FnMut() -> Result<R, Error>,
+    {
+        self.retry_policy
+            .retry(call, |err| log_api_error(&format!("{}_retry", stage), err))
+    }
 }
 
-impl<R, T, ENV, OUT, ERR> LambdaRuntime<R, T, OUT> for DefaultRuntime<R, T, ENV, OUT, ERR>
+impl<R, T, ENV, IN, OUT, ERR, CF> LambdaRuntime<R, T, OUT>
+    for DefaultRuntime<R, T, ENV, IN, OUT, ERR, CF>
 where
     R: LambdaAPIResponse,
-    T: Transport<R>,
+    T: Transport<R> + Sync,
     ENV: RuntimeEnvVars,
-    // I: LambdaContext,
-    ERR: Display,
+    IN: DeserializeOwned,
+    ERR: LambdaError,
     OUT: Serialize,
+    CF: ContextFactory<ENV, R>,
 {
     fn run(&mut self) {
-        // Run the app's initializer and check for errors
-        let init_result = (self.initializer)();
-        let lambda = match init_result {
-            Err(init_err) => {
-                // Try reporting to the Lambda service if there is an error during initialization
-                // TODO: Take error type and request from ERR
-                match self.initialization_error(Some("Runtime.InitError"), None) {
-                    Ok(r) => r,
-                    // If an error occurs during reporting the previous error, panic.
-                    Err(err) => panic!(
-                        "Failed to report initialization error. Error: {}, AWS Error: {}",
-                        &init_err, err
-                    ),
-                };
-                // After reporting an init error just panic.
-                panic!("Initialization Error: {}", &init_err);
+        // Run the app's initializer and exit with a code describing whether reporting its failure succeeded.
+        if let Err(err) = self.initialize() {
+            let exit_code = match err {
+                Error::Handler(_) => INIT_ERR_EXIT_CODE,
+                _ => INIT_REPORT_ERR_EXIT_CODE,
+            };
+            (self.exit_hook)(exit_code);
+            return;
+        }
+
+        // Start event processing loop as specified in [https://docs.aws.amazon.com/lambda/latest/dg/runtimes-custom.html]
+        // Failed cycles either exit (on server error or when the retry policy gives up) or continue (on any other error),
+        // backing off according to the retry policy so a persistently failing runtime API is not called in a hot loop.
+        let mut failures: u32 = 0;
+        while !self.shutdown.is_shutdown() {
+            match self.run_once() {
+                Ok(InvocationOutcome::Panicked { .. }) if self.exit_on_panic => {
+                    self.shutdown_handler();
+                    (self.exit_hook)(PANIC_EXIT_CODE);
+                    return;
+                }
+                Ok(_) => failures = 0,
+                Err(err) if self.retry_policy.should_exit(&err) => {
+                    self.shutdown_handler();
+                    (self.exit_hook)(CONTAINER_ERR_EXIT_CODE);
+                    return;
+                }
+                Err(_) => {
+                    failures = failures.saturating_add(1);
+                    std::thread::sleep(self.retry_policy.backoff_for(failures));
+                }
+            }
+        }
+        self.shutdown_handler();
+    }
+
+    fn run_once(&mut self) -> Result<InvocationOutcome, Error> {
+        self.initialize()?;
+
+        // Get the next event in the queue.
+        let next = self.next_invocation().map_err(logged("next"))?;
+        let next_resp = &next;
+        let request_id = match next_resp.aws_request_id() {
+            Some(rid) => rid,
+            None => {
+                let error_req = ErrorRequest::new(
+                    MISSING_REQ_ID_ERR_TYPE,
+                    "Missing Lambda-Runtime-Aws-Request-Id header",
+                );
+                self.initialization_error(&error_req)
+                    .map_err(logged("init_error"))?;
+                return Ok(InvocationOutcome::MissingRequestId);
             }
-            // On successfull init, unwrap the underlying closure (event handler)
-            Ok(event_handler) => event_handler,
         };
 
-        // Start event processing loop as specified in [https://docs.aws.amazon.com/lambda/latest/dg/runtimes-custom.html]
-        loop {
-            // Get the next event in the queue.
-            // Failing to get the next event will either panic (on server error) or continue (on client-error codes).
-            let next: Result<R, _> = self.next_invocation();
-            if next.is_err() {
-                // TODO - perhaps log the error
-                continue;
+        // Retrieve the event JSON and deserialize it into the handler's input type
+        let event: IN = match next_resp.event_response().map(serde_json::from_str) {
+            Some(Ok(ev)) => ev,
+            parse_result => {
+                let msg = match parse_result {
+                    Some(Err(err)) => err.to_string(),
+                    _ => "Missing event payload".to_string(),
+                };
+                self.unmarshal_error(request_id, &msg)
+                    .map_err(logged("error"))?;
+                return Ok(InvocationOutcome::UnmarshalError {
+                    request_id: request_id.to_string(),
+                });
             }
-            let next_resp = next.as_ref().unwrap();
-            let request_id = match next_resp.aws_request_id() {
-                Some(rid) => rid,
-                None => {
-                    // TODO - figure out what we'd like to do with the result returned from success/client-err api responses
-                    let _ = self.initialization_error(Some("Runtime.MissingRequestId"), None);
-                    continue;
-                }
-            };
+        };
 
-            // Create the context object for the lambda execution
-            // TODO - Design a way to pass a generic type implementing LambdaContext and use it to construct the context
-            let context = RefLambdaContext {
-                env_vars: &self.env_vars,
-                invo_resp: next_resp,
-            };
-            // Retrieve the event JSON
-            // TODO - deserialize? Currently user code should deserialize inside their handler
-            let event = next_resp.event_response();
+        // Create the response stream, which is only started if the handler writes to it
+        let url = format!(
+            "http://{}/{}/runtime/invocation/{}/response",
+            self.api_base, self.version, request_id
+        );
+        let transport = &self.transport;
+        let watchdog = Watchdog::default();
+        let opener = |content_type: &str| {
+            if !watchdog.start_streaming() {
+                return Err(Error::Timeout(
+                    "The invocation timed out before the response stream was started".to_string(),
+                ));
+            }
+            let trailers = format!("{}, {}", AWS_FUNC_ERR_TYPE, AWS_FUNC_ERR_BODY);
+            let headers = (
+                vec![AWS_FUNC_RESPONSE_MODE, "Content-Type", "Trailer"],
+                vec!["streaming", content_type, trailers.as_str()],
+            );
+            transport.post_chunked(&url, Some(headers))
+        };
+        let stream = ResponseStream::new(&opener);
+
+        // Create the cancellation token, which is cancelled by its own watchdog
+        let cancel_at = match self.cancellation_margin {
+            Some(margin) => next_resp
+                .deadline()
+                .map(|deadline| fire_at(deadline, margin)),
+            None => None,
+        };
+        let token = cancel_at.map(|_| CancellationToken::new());
+        let canceller = Watchdog::default();
+        let extensions = Extensions::new();
+
+        // Create the context object for the lambda execution using the context factory
+        let context = self.context_factory.create(RefLambdaContext {
+            env_vars: &self.env_vars,
+            invo_resp: next_resp,
+            response_stream: Some(&stream),
+            cancellation: token.as_ref(),
+            extensions: &extensions,
+            shared_state: &self.shared_state,
+        });
+
+        // Execute the event handler, wrapped by the layer stack
+        let lambda = self
+            .handler
+            .as_mut()
+            .expect("The event handler is set by a successful initialization");
+        let layers = &mut self.layers;
+        let started = Instant::now();
+        let deadline = match self.timeout_margin {
+            Some(margin) => next_resp
+                .deadline()
+                .map(|deadline| fire_at(deadline, margin)),
+            None => None,
+        };
+        let error_url = format!(
+            "http://{}/{}/runtime/invocation/{}/error",
+            self.api_base, self.version, request_id
+        );
+        let (lambda_output, timeout) = std::thread::scope(|scope| {
+            // Race the handler against the watchdog, which reports the timeout on its own thread.
+            // Once the response stream was started, the timeout is returned to be sent in its trailers instead.
+            let timeout = deadline.map(|deadline| {
+                let watchdog = &watchdog;
+                let error_url = &error_url;
+                scope.spawn(move || {
+                    watchdog.watch(deadline, || {
+                        let error_req = ErrorRequest::new(
+                            TIMEOUT_ERR_TYPE,
+                            &format!(
+                                "Invocation is about to time out in stage '{}' after {}ms",
+                                watchdog.stage(),
+                                started.elapsed().as_millis()
+                            ),
+                        );
+                        if !watchdog.is_streaming() {
+                            let result = serde_json::to_string(&error_req)
+                                .map_err(Error::from)
+                                .and_then(|body| {
+                                    send_error(transport, error_url, &body, TIMEOUT_ERR_TYPE)
+                                });
+                            log_error("timeout", &error_req, result.err().as_ref());
+                        }
+                        error_req
+                    })
+                })
+            });
+            if let (Some(cancel_at), Some(token)) = (cancel_at, &token) {
+                let canceller = &canceller;
+                scope.spawn(move || canceller.watch(cancel_at, || token.cancel()));
+            }
+            let lambda_output =
+                catch_panic(|| Next::new(layers, lambda.as_mut()).run(event, context));
+            canceller.finish();
+            watchdog.finish();
+            let timeout = timeout.and_then(|watcher| watcher.join().ok().flatten());
+            (lambda_output, timeout)
+        });
+        if let Some(error_req) = timeout {
+            // End a started response stream with the timeout in its trailers, it was not reported by the watchdog.
+            if stream.is_started() {
+                let result = self.end_stream(stream, Some(&error_req));
+                log_error("timeout", &error_req, result.as_ref().err());
+                result?;
+            }
+            return Ok(InvocationOutcome::TimedOut {
+                request_id: request_id.to_string(),
+            });
+        }
+        // Let the handler translate its error into the reported one, panics are reported as is.
+        let (lambda_output, panic) = match lambda_output {
+            Ok(output) => (output.map_err(|err| lambda.on_error(&err)), None),
+            Err(panic) => (Err(panic.to_error_request()), Some(panic.message)),
+        };
 
-            // Execute the event handler
-            let lambda_output = lambda(event, context);
+        // End the response stream if the handler started it, reporting errors in its trailers.
+        if stream.is_started() {
+            let error_req = lambda_output.err();
+            self.end_stream(stream, error_req.as_ref())
+                .map_err(logged("stream"))?;
+            if let Some(message) = panic {
+                return Ok(InvocationOutcome::Panicked {
+                    request_id: request_id.to_string(),
+                    message,
+                });
+            }
+            return Ok(InvocationOutcome::Streamed {
+                request_id: request_id.to_string(),
+                error_type: error_req.map(|req| req.error_type),
+            });
+        }
 
-            // TODO - figure out what we'd like to do with the result returned from success/client-err api responses (e.g: log, run a user defined callback...)
-            let _ = match lambda_output {
-                Ok(out) => self.invocation_response(request_id, &out),
-                // TODO - pass an ErrorRequest json
+        // Report the result of the handler, a response that fails to serialize is reported as an error.
+        match lambda_output {
+            Ok(out) => match (self.serializer)(&out) {
+                Ok(serialized) => {
+                    self.post_response(request_id, &serialized)
+                        .map_err(logged("response"))?;
+                    Ok(InvocationOutcome::Response {
+                        request_id: request_id.to_string(),
+                    })
+                }
                 Err(err) => {
-                    let _err = format!("{}", &err);
-                    self.invocation_error(request_id, Some(&_err), Some(&_err))
+                    let error_req = ErrorRequest::new(
+                        SERIALIZATION_ERR_TYPE,
+                        &format!("Failed serializing response: {}", err),
+                    );
+                    self.invocation_error(request_id, &error_req)
+                        .map_err(logged("error"))?;
+                    Ok(InvocationOutcome::SerializationError {
+                        request_id: request_id.to_string(),
+                    })
                 }
-            };
+            },
+            Err(error_req) => {
+                self.invocation_error(request_id, &error_req)
+                    .map_err(logged("error"))?;
+                if let Some(message) = panic {
+                    return Ok(InvocationOutcome::Panicked {
+                        request_id: request_id.to_string(),
+                        message,
+                    });
+                }
+                Ok(InvocationOutcome::HandlerError {
+                    request_id: request_id.to_string(),
+                    error_type: error_req.error_type,
+                })
+            }
+        }
+    }
+
+    fn run_until<P>(&mut self, mut predicate: P) -> Result<(), Error>
+    where
+        P: FnMut(&InvocationOutcome) -> bool,
+    {
+        while !self.shutdown.is_shutdown() {
+            if predicate(&self.run_once()?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_n(&mut self, n: usize) -> Result<Vec<InvocationOutcome>, Error> {
+        let mut outcomes = Vec::with_capacity(n);
+        while outcomes.len() < n && !self.shutdown.is_shutdown() {
+            outcomes.push(self.run_once()?);
         }
+        Ok(outcomes)
     }
 
     fn next_invocation(&mut self) -> Result<R, Error> {
@@ -235,76 +874,46 @@ where
             "http://{}/{}/runtime/invocation/next",
             self.api_base, self.version
         );
-        let resp = self.transport.get(&url, None, None)?;
+        let resp = self.retry("next", || {
+            let resp = self.transport.get(&url, None, None)?;
 
-        handle_response!(resp);
+            handle_response!(resp);
 
-        // If AWS returns the "Lambda-Runtime-Trace-Id" header, set its value to the -
-        // "_X_AMZN_TRACE_ID" env var
-        if let Some(req_id) = resp.trace_id() {
-            set_var(OsStr::new("_X_AMZN_TRACE_ID"), OsStr::new(req_id));
-            self.env_vars.set_trace_id(Some(req_id));
-        };
+            Ok(resp)
+        })?;
+
+        // Pass the value of the "Lambda-Runtime-Trace-Id" header on to the env-vars instance - and to the "_X_AMZN_TRACE_ID" env var if enabled,
+        // clearing the trace id of the previous invocation if AWS did not return one
+        let trace_id = resp.trace_id();
+        if self.trace_env_var {
+            match trace_id {
+                Some(trace_id) => std::env::set_var(TRACE_ID_ENV_VAR, trace_id),
+                None => std::env::remove_var(TRACE_ID_ENV_VAR),
+            }
+        }
+        self.env_vars.set_trace_id(trace_id);
 
         Ok(resp)
     }
 
     fn invocation_response(&self, request_id: &str, response: &OUT) -> Result<R, Error> {
-        let url = format!(
-            "http://{}/{}/runtime/invocation/{}/response",
-            self.api_base, self.version, request_id
-        );
-        // TODO - Utilize a user-defined JSON serializer?
-        let serialized = match serde_json::to_string(response) {
-            Ok(ser) => ser,
-            Err(err) => {
-                return Err(Error::new(format!(
-                    "Failed serializing output to JSON. {}",
-                    err
-                )))
-            }
-        };
-        let resp = self.transport.post(&url, Some(&serialized), None)?;
-
-        handle_response!(resp);
-
-        Ok(resp)
+        let serialized = (self.serializer)(response)?;
+        self.post_response(request_id, &serialized)
     }
 
-    fn initialization_error(
-        &self,
-        error_type: Option<&str>,
-        error_req: Option<&str>,
-    ) -> Result<R, Error> {
+    fn initialization_error(&self, error_req: &ErrorRequest) -> Result<R, Error> {
         let url = format!(
             "http://{}/{}/runtime/init/error",
             self.api_base, self.version
         );
-        let headers = error_type.map(|et| (vec![AWS_FUNC_ERR_TYPE], vec![et]));
-
-        let resp = self.transport.post(&url, error_req, headers)?;
-
-        handle_response!(resp);
-
-        Ok(resp)
+        self.post_error(&url, error_req)
     }
 
-    fn invocation_error(
-        &self,
-        request_id: &str,
-        error_type: Option<&str>,
-        error_req: Option<&str>,
-    ) -> Result<R, Error> {
+    fn invocation_error(&self, request_id: &str, error_req: &ErrorRequest) -> Result<R, Error> {
         let url = format!(
             "http://{}/{}/runtime/invocation/{}/error",
             self.api_base, self.version, request_id
         );
-        let headers = error_type.map(|et| (vec![AWS_FUNC_ERR_TYPE], vec![et]));
-
-        let resp = self.transport.post(&url, error_req, headers)?;
-
-        handle_response!(resp);
-
-        Ok(resp)
+        self.post_error(&url, error_req)
     }
 }
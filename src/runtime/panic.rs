@@ -0,0 +1,133 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::error::{ErrorRequest, PANIC_ERR_TYPE};
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Once;
+
+/// Describes a panic caught by [`catch_panic`].
+#[derive(Clone, Debug)]
+pub(crate) struct PanicDetails {
+    pub message: String,
+    /// The `file:line:column` the panic was raised at.
+    pub location: Option<String>,
+    /// The lines of the backtrace, which is only captured if enabled by the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` env-vars.
+    pub backtrace: Vec<String>,
+}
+
+impl PanicDetails {
+    /// Creates the [`ErrorRequest`] reporting the panic as a `Runtime.Panic` error,
+    /// with the location of the panic and its backtrace as the stack trace.
+    pub fn to_error_request(&self) -> ErrorRequest {
+        let mut error_req = ErrorRequest::new(PANIC_ERR_TYPE, &self.message);
+        error_req.stack_trace.extend(self.location.iter().cloned());
+        error_req.stack_trace.extend(self.backtrace.iter().cloned());
+        error_req
+    }
+}
+
+thread_local! {
+    /// Set while running a closure passed to [`catch_panic`], limiting the panic hook to the panics it catches.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// The location and backtrace of the last panic recorded by the panic hook.
+    static LAST_PANIC: RefCell<Option<(Option<String>, Vec<String>)>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Chains a panic hook recording the location and backtrace of panics caught by [`catch_panic`],
+/// which are otherwise unavailable once the stack was unwound. The previous hook is still called, e.g: to print the panic.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) {
+                let location = info.location().map(|loc| loc.to_string());
+                let backtrace = Backtrace::capture();
+                let lines = match backtrace.status() {
+                    BacktraceStatus::Captured => trim_backtrace(&backtrace.to_string()),
+                    _ => Vec::new(),
+                };
+                LAST_PANIC.with(|last| *last.borrow_mut() = Some((location, lines)));
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Returns the lines of a backtrace captured by the panic hook without the frames of the hook and the panic machinery
+/// (`std::panicking` and `core::panicking`), so it starts at the frame that raised the panic.
+fn trim_backtrace(backtrace: &str) -> Vec<String> {
+    let lines: Vec<&str> = backtrace.lines().map(str::trim).collect();
+    // The hook's frames precede the panic machinery, which directly precedes the frame that raised the panic
+    let start = lines
+        .iter()
+        .position(|line| is_panic_frame(line))
+        .and_then(|entry| {
+            lines[entry..]
+                .iter()
+                .position(|line| is_frame(line) && !is_panic_frame(line))
+                .map(|offset| entry + offset)
+        })
+        .unwrap_or(0);
+    lines[start..].iter().map(|line| line.to_string()).collect()
+}
+
+/// Returns `true` for the line starting a frame of a backtrace, e.g: `12: my_crate::handle`,
+/// as opposed to the `at file:line:column` lines following it.
+fn is_frame(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((index, _)) => !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Returns `true` for the line starting a frame of the standard library's panic machinery.
+fn is_panic_frame(line: &str) -> bool {
+    is_frame(line)
+        && [
+            "panicking::",
+            "rust_begin_unwind",
+            "__rust_end_short_backtrace",
+        ]
+        .iter()
+        .any(|symbol| line.contains(symbol))
+}
+
+/// Extracts the message of a panic payload, which is either a `&str` or a [`String`] for panics raised by [`panic!`].
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Calls `f`, catching a panic it raises along with its message, location and backtrace.
+///
+/// The closure is assumed to be unwind safe, since a runtime that keeps running after a panic
+/// leaves it to the event handler to tolerate any state the panic left behind.
+pub(crate) fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, PanicDetails> {
+    install_hook();
+    let was_catching = CATCHING.with(|catching| catching.replace(true));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(was_catching));
+
+    result.map_err(|payload| {
+        let (location, backtrace) = LAST_PANIC
+            .with(|last| last.borrow_mut().take())
+            .unwrap_or_default();
+        PanicDetails {
+            message: panic_message(payload.as_ref()),
+            location,
+            backtrace,
+        }
+    })
+}
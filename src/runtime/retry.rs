@@ -0,0 +1,164 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::error::Error;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// The action taken by a runtime once a runtime API call failed on all of its attempts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GiveUp {
+    /// Return the error to the runtime loop, which logs it and backs off before moving on to the next event.
+    Continue,
+    /// Treat the error as non-recoverable, making the runtime loop exit through its exit hook
+    /// as it does on a container error.
+    Exit,
+}
+
+/// Defines how a runtime retries runtime API calls that failed due to a transport error,
+/// waiting an exponentially growing backoff with jitter between attempts.
+///
+/// Only [`Error::Transport`] errors are retried, other errors are returned immediately -
+/// container errors must make the runtime exit and client errors would fail again.
+/// Started response streams are never retried as their body was already consumed.
+///
+/// Cycles of the runtime loop that still fail are backed off as well, using the number of consecutive failed cycles as the attempt,
+/// e.g: when the runtime API keeps answering with a client error.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts made for each call, including the first one.
+    pub max_attempts: u32,
+    /// The backoff before the first retry.
+    pub initial_backoff: Duration,
+    /// The upper bound of the backoff between attempts.
+    pub max_backoff: Duration,
+    /// The factor the backoff is multiplied by after each retry.
+    pub multiplier: u32,
+    /// Whether to randomize each backoff between half and all of its value, spreading out retries of concurrent runtimes.
+    pub jitter: bool,
+    /// The action taken once all attempts failed.
+    pub give_up: GiveUp,
+}
+
+impl Default for RetryPolicy {
+    /// Makes up to 5 attempts with a backoff starting at 50ms and doubling up to 2s, with jitter.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2,
+            jitter: true,
+            give_up: GiveUp::Continue,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy making a single attempt for each call.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the maximum number of attempts made for each call, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the initial and the maximum backoff between attempts.
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the factor the backoff is multiplied by after each retry.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Enables or disables randomizing the backoff.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the action taken once all attempts failed.
+    pub fn give_up(mut self, give_up: GiveUp) -> Self {
+        self.give_up = give_up;
+        self
+    }
+
+    /// Returns `true` if a call that failed with `err` may be attempted again.
+    pub fn is_retryable(err: &Error) -> bool {
+        matches!(err, Error::Transport { .. })
+    }
+
+    /// Returns `true` if the runtime loop should exit after a call failed with `err`,
+    /// either because it is a container error or because retrying it was given up according to [`RetryPolicy::give_up`].
+    pub fn should_exit(&self, err: &Error) -> bool {
+        err.is_container_err() || (self.give_up == GiveUp::Exit && Self::is_retryable(err))
+    }
+
+    /// Returns the backoff to wait after the given failed attempt, counted from 1.
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1)
+            .saturating_pow(attempt.saturating_sub(1));
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        if !self.jitter {
+            return backoff;
+        }
+        // Equal jitter - keeps at least half of the backoff so retries never hot loop
+        let half = backoff / 2;
+        let nanos = (backoff - half).as_nanos() as u64;
+        if nanos == 0 {
+            return backoff;
+        }
+        half + Duration::from_nanos(random_u64() % (nanos + 1))
+    }
+
+    /// Calls `call` until it succeeds, fails with an error that is not retryable or all attempts were made,
+    /// sleeping the backoff between attempts. `on_retry` is called with every error that is retried.
+    pub fn retry<T, F, L>(&self, mut call: F, mut on_retry: L) -> Result<T, Error>
+    where
+        F: FnMut() -> Result<T, Error>,
+        L: FnMut(&Error),
+    {
+        let mut attempt = 1;
+        loop {
+            match call() {
+                Err(err) if attempt < self.max_attempts && Self::is_retryable(&err) => {
+                    on_retry(&err);
+                    std::thread::sleep(self.backoff_for(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Returns a random number using the randomly seeded hasher of the standard library, avoiding a dependency on a RNG crate.
+pub(crate) fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
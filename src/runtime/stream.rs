@@ -0,0 +1,118 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_BODY, AWS_FUNC_ERR_TYPE};
+use crate::error::{Error, ErrorRequest};
+use crate::transport::ChunkedRequest;
+
+use std::cell::RefCell;
+use std::io::Write;
+
+/// The content type of a streamed response, unless set otherwise using [`ResponseStream::start`].
+pub static DEFAULT_STREAM_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// A function starting the chunked request used by a [`ResponseStream`], given the content type of the response.
+pub type StreamOpener<'a, R> = dyn Fn(&str) -> Result<Box<dyn ChunkedRequest<R>>, Error> + 'a;
+
+/// A sink for [streaming a response](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-custom.html#runtimes-custom-response-streaming)
+/// back to the Lambda service, handed to the event handler through [`crate::data::context::RefLambdaContext`].
+///
+/// The response is started lazily by the first write (or by calling [`ResponseStream::start`]),
+/// in which case the runtime ignores the output returned by the handler and ends the stream once the handler returns.
+/// If the handler returns an error after the stream was started, the error is sent in the trailers of the response.
+///
+/// Each write is sent as a separate chunk, writing is implemented for a shared reference so it can be used from the context.
+pub struct ResponseStream<'a, R: LambdaAPIResponse> {
+    opener: &'a StreamOpener<'a, R>,
+    body: RefCell<Option<Box<dyn ChunkedRequest<R>>>>,
+}
+
+impl<'a, R: LambdaAPIResponse> ResponseStream<'a, R> {
+    /// Creates a stream that calls `opener` when started.
+    pub fn new(opener: &'a StreamOpener<'a, R>) -> Self {
+        Self {
+            opener,
+            body: RefCell::new(None),
+        }
+    }
+
+    /// Starts the response with the given content type, fails if the response has already been started.
+    pub fn start(&self, content_type: &str) -> std::io::Result<()> {
+        let mut body = self.body.borrow_mut();
+        if body.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "Response stream already started",
+            ));
+        }
+        *body = Some((self.opener)(content_type).map_err(std::io::Error::other)?);
+        Ok(())
+    }
+
+    /// Returns `true` if the response has been started.
+    #[inline]
+    pub fn is_started(&self) -> bool {
+        self.body.borrow().is_some()
+    }
+
+    /// Ends a started response, sending the optional `error_req` in its trailers.
+    pub(crate) fn finish(self, error_req: Option<&ErrorRequest>) -> Result<R, Error> {
+        let body = match self.body.into_inner() {
+            Some(body) => body,
+            None => {
+                return Err(Error::Transport {
+                    msg: "Response stream was not started".to_string(),
+                    source: None,
+                })
+            }
+        };
+        let error_req = match error_req {
+            Some(req) => req,
+            None => return body.finish(None),
+        };
+        let serialized = base64_encode(serde_json::to_string(error_req)?.as_bytes());
+        let trailers = (
+            vec![AWS_FUNC_ERR_TYPE, AWS_FUNC_ERR_BODY],
+            vec![error_req.error_type.as_str(), serialized.as_str()],
+        );
+        body.finish(Some(trailers))
+    }
+}
+
+impl<'a, R: LambdaAPIResponse> Write for &ResponseStream<'a, R> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.is_started() {
+            self.start(DEFAULT_STREAM_CONTENT_TYPE)?;
+        }
+        match self.body.borrow_mut().as_mut() {
+            Some(body) => body.write(buf),
+            None => unreachable!(),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.body.borrow_mut().as_mut() {
+            Some(body) => body.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Encodes `input` using the standard base64 alphabet with padding, as expected by the error body trailer.
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
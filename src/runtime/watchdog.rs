@@ -0,0 +1,94 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The error type reported to the Lambda service by the watchdog when an invocation is about to time out.
+pub static TIMEOUT_ERR_TYPE: &str = "Runtime.Timeout";
+
+#[derive(Default)]
+struct WatchdogState {
+    finished: bool,
+    fired: bool,
+    /// Set once the handler started streaming its response, which is refused after the watchdog fired.
+    streaming: bool,
+}
+
+/// Races the event handler of a single invocation against a point in time shortly before its deadline.
+///
+/// Whichever comes first wins - either the handler finishes and the watchdog stands down, or the watchdog fires
+/// (e.g: cancelling the invocation's [`crate::runtime::cancel::CancellationToken`] or reporting a timeout).
+#[derive(Default)]
+pub(crate) struct Watchdog {
+    state: Mutex<WatchdogState>,
+    changed: Condvar,
+}
+
+impl Watchdog {
+    fn lock(&self) -> MutexGuard<'_, WatchdogState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Blocks until `fire_at` or until [`Watchdog::finish`] is called, whichever comes first.
+    /// Calls `on_fire` and returns its result if `fire_at` was reached before the handler finished.
+    pub fn watch<T, F: FnOnce() -> T>(&self, fire_at: Instant, on_fire: F) -> Option<T> {
+        let mut state = self.lock();
+        loop {
+            if state.finished {
+                return None;
+            }
+            let now = Instant::now();
+            if now >= fire_at {
+                break;
+            }
+            state = match self.changed.wait_timeout(state, fire_at - now) {
+                Ok((state, _)) => state,
+                Err(err) => err.into_inner().0,
+            };
+        }
+        state.fired = true;
+        drop(state);
+        Some(on_fire())
+    }
+
+    /// Signals that the handler finished, returns `true` if the watchdog has already fired.
+    pub fn finish(&self) -> bool {
+        let mut state = self.lock();
+        state.finished = true;
+        self.changed.notify_all();
+        state.fired
+    }
+
+    /// Records that the handler started streaming its response, returns `false` if the watchdog has already fired.
+    pub fn start_streaming(&self) -> bool {
+        let mut state = self.lock();
+        if state.fired {
+            return false;
+        }
+        state.streaming = true;
+        true
+    }
+
+    /// Returns `true` if the handler started streaming its response.
+    pub fn is_streaming(&self) -> bool {
+        self.lock().streaming
+    }
+
+    /// Describes the stage the handler is in, reported along with the timeout.
+    pub fn stage(&self) -> &'static str {
+        match self.is_streaming() {
+            true => "streaming response",
+            false => "handler",
+        }
+    }
+}
+
+/// Returns the instant `margin` before the given `deadline` - a duration since the unix epoch.
+pub(crate) fn fire_at(deadline: Duration, margin: Duration) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Instant::now() + deadline.saturating_sub(margin).saturating_sub(now)
+}
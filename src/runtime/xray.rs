@@ -0,0 +1,261 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::context::{ContextFactory, LambdaContext};
+use crate::data::env::RuntimeEnvVars;
+use crate::data::response::LambdaAPIResponse;
+use crate::data::trace::TraceHeader;
+use crate::error::{Error, LambdaError};
+use crate::runtime::layer::{Layer, Next};
+use crate::runtime::retry::random_u64;
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The env-var holding the address of the X-Ray daemon, set by the Lambda service when active tracing is enabled.
+pub static XRAY_DAEMON_ADDRESS_ENV_VAR: &str = "AWS_XRAY_DAEMON_ADDRESS";
+
+/// The header preceding each segment document sent to the X-Ray daemon.
+static DAEMON_HEADER: &str = "{\"format\": \"json\", \"version\": 1}\n";
+
+/// Sends [segment documents](https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html)
+/// to the X-Ray daemon over UDP.
+#[derive(Debug)]
+pub struct XRayEmitter {
+    socket: UdpSocket,
+    daemon: SocketAddr,
+}
+
+impl XRayEmitter {
+    /// Creates an emitter sending documents to `daemon_address`, either a `host:port` address -
+    /// or a list of addresses by protocol such as `tcp:127.0.0.1:2000 udp:127.0.0.1:2000`, in which case the UDP address is used.
+    pub fn new(daemon_address: &str) -> Result<Self, Error> {
+        let address = daemon_address
+            .split_whitespace()
+            .find_map(|address| address.strip_prefix("udp:"))
+            .unwrap_or_else(|| daemon_address.trim());
+        let daemon = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| Error::Env(format!("Invalid X-Ray daemon address: {}", address)))?;
+        let local = match daemon {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(local)
+            .map_err(|err| Error::transport("Failed binding the X-Ray socket", err))?;
+        Ok(XRayEmitter { socket, daemon })
+    }
+
+    /// Creates an emitter sending documents to the address set in the `AWS_XRAY_DAEMON_ADDRESS` env-var.
+    pub fn from_env() -> Result<Self, Error> {
+        match std::env::var(XRAY_DAEMON_ADDRESS_ENV_VAR) {
+            Ok(address) => Self::new(&address),
+            Err(_) => Err(Error::Env(format!(
+                "{} is not set",
+                XRAY_DAEMON_ADDRESS_ENV_VAR
+            ))),
+        }
+    }
+
+    /// Sends a single segment document to the daemon.
+    pub fn send(&self, document: &serde_json::Value) -> Result<(), Error> {
+        let packet = format!("{}{}", DAEMON_HEADER, document);
+        self.socket
+            .send_to(packet.as_bytes(), self.daemon)
+            .map_err(|err| Error::transport("Failed sending the X-Ray segment", err))?;
+        Ok(())
+    }
+}
+
+/// Returns the current time in seconds since the unix epoch, as used by segment documents.
+fn epoch_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Creates subsegments within the segment or subsegment that is the parent of its trace header.
+///
+/// The [`XRayLayer`] inserts a tracer into the [`crate::data::extensions::Extensions`] of each traced invocation,
+/// which the event handler uses to time its outbound calls.
+#[derive(Clone, Debug)]
+pub struct XRayTracer {
+    emitter: Arc<XRayEmitter>,
+    trace: TraceHeader,
+}
+
+impl XRayTracer {
+    /// Creates a tracer for the given trace header, usually the one of the invocation.
+    pub fn new(emitter: Arc<XRayEmitter>, trace: TraceHeader) -> Self {
+        XRayTracer { emitter, trace }
+    }
+
+    /// The trace header whose parent is the parent of the created subsegments.
+    pub fn trace_header(&self) -> &TraceHeader {
+        &self.trace
+    }
+
+    /// Starts a subsegment named `name`, which is sent to the daemon once dropped.
+    pub fn subsegment(&self, name: &str) -> Subsegment {
+        Subsegment {
+            tracer: self.clone(),
+            id: format!("{:016x}", random_u64()),
+            name: name.to_string(),
+            start_time: epoch_secs(),
+            namespace: None,
+            error: false,
+            fault: false,
+        }
+    }
+}
+
+/// A guard timing a unit of work, e.g: an outbound call, sent to the X-Ray daemon as a subsegment once dropped.
+///
+/// Subsegments are only sent for sampled traces that have a parent, and are marked as a fault if dropped while panicking.
+#[derive(Debug)]
+pub struct Subsegment {
+    tracer: XRayTracer,
+    id: String,
+    name: String,
+    start_time: f64,
+    namespace: Option<String>,
+    error: bool,
+    fault: bool,
+}
+
+impl Subsegment {
+    /// The id of the subsegment.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns a tracer creating subsegments nested in this subsegment.
+    pub fn tracer(&self) -> XRayTracer {
+        XRayTracer::new(
+            Arc::clone(&self.tracer.emitter),
+            self.tracer.trace.with_parent(&self.id),
+        )
+    }
+
+    /// Returns the trace header to propagate to a downstream service called within this subsegment,
+    /// e.g: as the `X-Amzn-Trace-Id` header.
+    pub fn trace_header(&self) -> TraceHeader {
+        self.tracer.trace.with_parent(&self.id)
+    }
+
+    /// Sets the namespace of the subsegment, `aws` for AWS SDK calls or `remote` for other downstream calls.
+    pub fn set_namespace(&mut self, namespace: &str) {
+        self.namespace = Some(namespace.to_string());
+    }
+
+    /// Marks the subsegment as failed with a client error.
+    pub fn set_error(&mut self) {
+        self.error = true;
+    }
+
+    /// Marks the subsegment as failed with a server error.
+    pub fn set_fault(&mut self) {
+        self.fault = true;
+    }
+
+    /// Returns the segment document of the subsegment ending at `end_time`, or `None` if it is not sampled or has no parent.
+    fn document(&self, end_time: f64) -> Option<serde_json::Value> {
+        let trace = &self.tracer.trace;
+        if trace.sampled != Some(true) {
+            return None;
+        }
+        let mut document = serde_json::json!({
+            "name": self.name,
+            "id": self.id,
+            "trace_id": trace.root,
+            "parent_id": trace.parent.as_ref()?,
+            "type": "subsegment",
+            "start_time": self.start_time,
+            "end_time": end_time,
+        });
+        if let Some(fields) = document.as_object_mut() {
+            if let Some(namespace) = &self.namespace {
+                fields.insert("namespace".to_string(), namespace.as_str().into());
+            }
+            if self.error {
+                fields.insert("error".to_string(), true.into());
+            }
+            if self.fault {
+                fields.insert("fault".to_string(), true.into());
+            }
+        }
+        Some(document)
+    }
+}
+
+impl Drop for Subsegment {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.fault = true;
+        }
+        // Tracing is best effort, a failure to reach the daemon must not fail the invocation
+        if let Some(document) = self.document(epoch_secs()) {
+            let _ = self.tracer.emitter.send(&document);
+        }
+    }
+}
+
+/// A [`Layer`] timing each invocation of the event handler as an X-Ray subsegment of the function's segment,
+/// named after the function and marked as a fault if the handler fails or panics.
+///
+/// The trace header is parsed from the trace id of the invocation, invocations without a valid trace id are not traced.
+/// An [`XRayTracer`] for creating subsegments nested in the handler's subsegment is inserted into the context's extensions,
+/// for context types exposing them through [`LambdaContext::extensions`].
+#[derive(Clone, Debug)]
+pub struct XRayLayer {
+    emitter: Arc<XRayEmitter>,
+}
+
+impl XRayLayer {
+    /// Creates a layer sending subsegments using the given emitter.
+    pub fn new(emitter: XRayEmitter) -> Self {
+        XRayLayer {
+            emitter: Arc::new(emitter),
+        }
+    }
+
+    /// Creates a layer sending subsegments to the address set in the `AWS_XRAY_DAEMON_ADDRESS` env-var, see [`XRayEmitter::from_env`].
+    pub fn from_env() -> Result<Self, Error> {
+        XRayEmitter::from_env().map(Self::new)
+    }
+}
+
+impl<IN, ENV, R, OUT, ERR, CF> Layer<IN, ENV, R, OUT, ERR, CF> for XRayLayer
+where
+    ENV: RuntimeEnvVars,
+    R: LambdaAPIResponse,
+    ERR: LambdaError,
+    CF: ContextFactory<ENV, R>,
+{
+    fn call(
+        &mut self,
+        event: IN,
+        context: CF::Context<'_>,
+        next: Next<IN, ENV, R, OUT, ERR, CF>,
+    ) -> Result<OUT, ERR> {
+        let trace = match context.parse_trace_header() {
+            Ok(Some(trace)) => trace,
+            _ => return next.run(event, context),
+        };
+        let tracer = XRayTracer::new(Arc::clone(&self.emitter), trace);
+        let mut subsegment = tracer.subsegment(context.function_name().unwrap_or("handler"));
+        if let Some(extensions) = context.extensions() {
+            extensions.insert(subsegment.tracer());
+        }
+        let result = next.run(event, context);
+        if result.is_err() {
+            subsegment.set_fault();
+        }
+        result
+    }
+}
@@ -0,0 +1,504 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::env::{InitializationType, LambdaRuntimeEnv};
+use crate::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
+use crate::error::Error;
+#[cfg(feature = "async")]
+use crate::transport::AsyncTransport;
+use crate::transport::{ChunkedRequest, Transport};
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The time until the deadline of an event created by [`MockResponse::event`].
+pub static DEFAULT_MOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns a [`LambdaRuntimeEnv`] populated with fixed values, that does not read the process' environment variables.
+/// The runtime API address is set to `runtime_api`.
+pub fn mock_env(runtime_api: &str) -> LambdaRuntimeEnv {
+    LambdaRuntimeEnv {
+        handler: Some("bootstrap".to_string()),
+        trace_id: None,
+        region: Some("us-east-1".to_string()),
+        execution_env: None,
+        function_name: Some("mock-function".to_string()),
+        function_memory_size: Some(128),
+        function_version: Some("$LATEST".to_string()),
+        initialization_type: InitializationType::OnDemand,
+        log_group_name: Some("/aws/lambda/mock-function".to_string()),
+        log_stream_name: Some("mock-stream".to_string()),
+        access_key: None,
+        access_key_id: None,
+        secret_access_key: None,
+        session_token: None,
+        runtime_api: Some(runtime_api.to_string()),
+        task_root: Some("/var/task".to_string()),
+        runtime_dir: Some("/var/runtime".to_string()),
+        tz: Some(":UTC".to_string()),
+    }
+}
+
+/// A programmable implementation of [`LambdaAPIResponse`] returned by [`MockTransport`].
+#[derive(Clone, Debug, Default)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: Option<String>,
+    pub request_id: Option<String>,
+    pub deadline: Option<Duration>,
+    pub invoked_function_arn: Option<String>,
+    pub trace_id: Option<String>,
+    pub client_context: Option<String>,
+    pub cognito_identity: Option<String>,
+}
+
+impl MockResponse {
+    /// Creates a response with the given status code and body, and no headers.
+    pub fn new(status: u16, body: Option<&str>) -> Self {
+        MockResponse {
+            status,
+            body: body.map(|b| b.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a next invocation response carrying an event with the given request id and JSON body,
+    /// whose deadline is [`DEFAULT_MOCK_TIMEOUT`] from now.
+    pub fn event(request_id: &str, body: &str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        MockResponse {
+            request_id: Some(request_id.to_string()),
+            deadline: Some(now + DEFAULT_MOCK_TIMEOUT),
+            ..Self::new(200, Some(body))
+        }
+    }
+
+    /// Creates the response the runtime API sends after accepting a response or an error.
+    pub fn accepted() -> Self {
+        Self::new(202, Some(r#"{"status":"OK"}"#))
+    }
+
+    /// Creates a client error response with an `ErrorResponse` body.
+    pub fn client_error(status: u16, error_type: &str, error_message: &str) -> Self {
+        let body = serde_json::json!({
+            "errorMessage": error_message,
+            "errorType": error_type,
+        });
+        Self::new(status, Some(&body.to_string()))
+    }
+
+    /// Creates a container error response.
+    pub fn container_error() -> Self {
+        Self::new(500, None)
+    }
+
+    /// Sets the deadline of the invocation to `timeout` from now.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.deadline = Some(now + timeout);
+        self
+    }
+
+    /// Sets the invoked function ARN.
+    pub fn invoked_function_arn(mut self, arn: &str) -> Self {
+        self.invoked_function_arn = Some(arn.to_string());
+        self
+    }
+
+    /// Sets the trace id.
+    pub fn trace_id(mut self, trace_id: &str) -> Self {
+        self.trace_id = Some(trace_id.to_string());
+        self
+    }
+
+    /// Sets the raw client context.
+    pub fn client_context(mut self, client_context: &str) -> Self {
+        self.client_context = Some(client_context.to_string());
+        self
+    }
+
+    /// Sets the raw cognito identity.
+    pub fn cognito_identity(mut self, cognito_identity: &str) -> Self {
+        self.cognito_identity = Some(cognito_identity.to_string());
+        self
+    }
+}
+
+impl LambdaAPIResponse for MockResponse {
+    #[inline(always)]
+    fn get_body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    #[inline(always)]
+    fn get_status_code(&self) -> u16 {
+        self.status
+    }
+
+    #[inline]
+    fn aws_request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+    #[inline]
+    fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+    #[inline]
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self.invoked_function_arn.as_deref()
+    }
+    #[inline]
+    fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+    #[inline]
+    fn client_context(&self) -> Option<&str> {
+        self.client_context.as_deref()
+    }
+    #[inline]
+    fn cognito_identity(&self) -> Option<&str> {
+        self.cognito_identity.as_deref()
+    }
+}
+
+/// A request sent through a [`MockTransport`].
+#[derive(Clone, Debug)]
+pub struct MockCall {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// The trailers sent after a chunked body, e.g: when streaming a response.
+    pub trailers: Vec<(String, String)>,
+}
+
+impl MockCall {
+    /// Returns the request id in the url of invocation responses and errors.
+    pub fn request_id(&self) -> Option<&str> {
+        let path = self.url.split("/runtime/invocation/").nth(1)?;
+        match path.split_once('/') {
+            Some((id, "response" | "error")) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the first header named `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the value of the `Lambda-Runtime-Function-Error-Type` header, or trailer for streamed responses.
+    pub fn error_type(&self) -> Option<&str> {
+        self.header(AWS_FUNC_ERR_TYPE).or_else(|| {
+            self.trailers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(AWS_FUNC_ERR_TYPE))
+                .map(|(_, value)| value.as_str())
+        })
+    }
+
+    fn is_response(&self) -> bool {
+        self.method == "POST" && self.url.ends_with("/response")
+    }
+
+    fn is_error(&self) -> bool {
+        self.method == "POST"
+            && self.url.contains("/runtime/invocation/")
+            && self.url.ends_with("/error")
+    }
+
+    fn is_init_error(&self) -> bool {
+        self.method == "POST" && self.url.ends_with("/runtime/init/error")
+    }
+}
+
+/// A scripted result of a [`MockTransport`] call, transport errors are kept as messages since [`Error`] is not [`Clone`].
+type Scripted = Result<MockResponse, String>;
+
+#[derive(Default)]
+struct MockState {
+    next: VecDeque<Scripted>,
+    posts: VecDeque<Scripted>,
+    calls: Vec<MockCall>,
+}
+
+/// An in-memory implementation of [`Transport`] - and of `AsyncTransport` with the `async` feature - returning programmable [`MockResponse`]s and recording every call.
+///
+/// GET requests (next invocation) are answered from a queue filled by [`MockTransport::push_next`],
+/// once it is empty a container error is returned - which makes the runtime loop exit.
+/// POST requests are answered from a queue filled by [`MockTransport::push_post`], or accepted once it is empty.
+///
+/// Clones share the same state, so a test can keep a clone of the transport passed to the runtime and inspect the recorded calls.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    /// Creates a transport with empty queues.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enqueues a response to a next invocation request.
+    pub fn push_next(&self, resp: MockResponse) -> &Self {
+        self.lock().next.push_back(Ok(resp));
+        self
+    }
+
+    /// Enqueues an event with the given request id and JSON body, see [`MockResponse::event`].
+    pub fn push_event(&self, request_id: &str, body: &str) -> &Self {
+        self.push_next(MockResponse::event(request_id, body))
+    }
+
+    /// Enqueues a transport error answering a next invocation request.
+    pub fn push_next_err(&self, msg: &str) -> &Self {
+        self.lock().next.push_back(Err(msg.to_string()));
+        self
+    }
+
+    /// Enqueues a response to a POST request (invocation response or error, or initialization error).
+    pub fn push_post(&self, resp: MockResponse) -> &Self {
+        self.lock().posts.push_back(Ok(resp));
+        self
+    }
+
+    /// Enqueues a transport error answering a POST request.
+    pub fn push_post_err(&self, msg: &str) -> &Self {
+        self.lock().posts.push_back(Err(msg.to_string()));
+        self
+    }
+
+    /// Returns all recorded calls in the order they were made.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.lock().calls.clone()
+    }
+
+    /// Returns the recorded invocation responses.
+    pub fn responses(&self) -> Vec<MockCall> {
+        self.filter_calls(MockCall::is_response)
+    }
+
+    /// Returns the recorded invocation errors.
+    pub fn errors(&self) -> Vec<MockCall> {
+        self.filter_calls(MockCall::is_error)
+    }
+
+    /// Returns the recorded initialization errors.
+    pub fn init_errors(&self) -> Vec<MockCall> {
+        self.filter_calls(MockCall::is_init_error)
+    }
+
+    /// Asserts that a response with the given JSON body was posted for `request_id`.
+    /// Bodies are compared as JSON values, so formatting differences are ignored.
+    pub fn assert_response(&self, request_id: &str, body: &str) {
+        let expected: serde_json::Value =
+            serde_json::from_str(body).expect("Expected body is not valid JSON");
+        let responses = self.responses();
+        let found = responses.iter().any(|call| {
+            call.request_id() == Some(request_id)
+                && call
+                    .body
+                    .as_deref()
+                    .and_then(|b| serde_json::from_str(b).ok())
+                    == Some(expected.clone())
+        });
+        assert!(
+            found,
+            "No response with body {} was posted for request {}. Posted responses: {:?}",
+            body, request_id, responses
+        );
+    }
+
+    /// Asserts that an error of the given type was posted for `request_id`.
+    pub fn assert_error(&self, request_id: &str, error_type: &str) {
+        let errors = self.errors();
+        let found = errors.iter().any(|call| {
+            call.request_id() == Some(request_id) && call.error_type() == Some(error_type)
+        });
+        assert!(
+            found,
+            "No error of type {} was posted for request {}. Posted errors: {:?}",
+            error_type, request_id, errors
+        );
+    }
+
+    /// Asserts that an initialization error of the given type was posted.
+    pub fn assert_init_error(&self, error_type: &str) {
+        let errors = self.init_errors();
+        let found = errors
+            .iter()
+            .any(|call| call.error_type() == Some(error_type));
+        assert!(
+            found,
+            "No initialization error of type {} was posted. Posted errors: {:?}",
+            error_type, errors
+        );
+    }
+
+    /// Asserts that no invocation or initialization errors were posted.
+    pub fn assert_no_errors(&self) {
+        let errors: Vec<MockCall> = self
+            .calls()
+            .into_iter()
+            .filter(|call| call.is_error() || call.is_init_error())
+            .collect();
+        assert!(
+            errors.is_empty(),
+            "Unexpected errors were posted: {:?}",
+            errors
+        );
+    }
+
+    fn filter_calls(&self, pred: fn(&MockCall) -> bool) -> Vec<MockCall> {
+        self.lock()
+            .calls
+            .iter()
+            .filter(|c| pred(c))
+            .cloned()
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        // A panicking test thread should not hide the recorded calls from other threads
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn record(&self, call: MockCall) -> Result<MockResponse, Error> {
+        let mut state = self.lock();
+        let scripted = match call.method.as_str() {
+            "GET" => state
+                .next
+                .pop_front()
+                .unwrap_or_else(|| Ok(MockResponse::container_error())),
+            _ => state
+                .posts
+                .pop_front()
+                .unwrap_or_else(|| Ok(MockResponse::accepted())),
+        };
+        state.calls.push(call);
+        scripted.map_err(|msg| Error::Transport { msg, source: None })
+    }
+}
+
+/// Copies the borrowed headers accepted by [`Transport`] methods.
+fn own_headers(headers: Option<(Vec<&str>, Vec<&str>)>) -> Vec<(String, String)> {
+    match headers {
+        Some((keys, values)) => keys
+            .iter()
+            .zip(values.iter())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+impl Transport<MockResponse> for MockTransport {
+    fn get(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<MockResponse, Error> {
+        self.record(MockCall {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: own_headers(headers),
+            body: body.map(|b| b.to_string()),
+            trailers: Vec::new(),
+        })
+    }
+
+    fn post(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<MockResponse, Error> {
+        self.record(MockCall {
+            method: "POST".to_string(),
+            url: url.to_string(),
+            headers: own_headers(headers),
+            body: body.map(|b| b.to_string()),
+            trailers: Vec::new(),
+        })
+    }
+
+    fn post_chunked(
+        &self,
+        url: &str,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<Box<dyn ChunkedRequest<MockResponse>>, Error> {
+        Ok(Box::new(MockChunkedRequest {
+            transport: self.clone(),
+            call: MockCall {
+                method: "POST".to_string(),
+                url: url.to_string(),
+                headers: own_headers(headers),
+                body: Some(String::new()),
+                trailers: Vec::new(),
+            },
+        }))
+    }
+}
+
+/// Resolves immediately with the scripted responses, so the async runtime can be tested with the same scripts.
+#[cfg(feature = "async")]
+impl AsyncTransport<MockResponse> for MockTransport {
+    fn get(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> impl std::future::Future<Output = Result<MockResponse, Error>> + Send {
+        std::future::ready(Transport::get(self, url, body, headers))
+    }
+
+    fn post(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> impl std::future::Future<Output = Result<MockResponse, Error>> + Send {
+        std::future::ready(Transport::post(self, url, body, headers))
+    }
+}
+
+/// A [`ChunkedRequest`] returned by [`MockTransport`], the call is recorded once the request is finished.
+struct MockChunkedRequest {
+    transport: MockTransport,
+    call: MockCall,
+}
+
+impl Write for MockChunkedRequest {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(body) = self.call.body.as_mut() {
+            body.push_str(&String::from_utf8_lossy(buf));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ChunkedRequest<MockResponse> for MockChunkedRequest {
+    fn finish(
+        mut self: Box<Self>,
+        trailers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<MockResponse, Error> {
+        self.call.trailers = own_headers(trailers);
+        self.transport.record(self.call)
+    }
+}
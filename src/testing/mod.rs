@@ -0,0 +1,8 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+/// In-memory implementations of [`crate::transport::Transport`] and [`crate::data::response::LambdaAPIResponse`] for unit tests.
+pub mod mock;
+/// An in-process HTTP server implementing the Lambda runtime API, used for end-to-end tests of runtimes.
+pub mod server;
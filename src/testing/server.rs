@@ -0,0 +1,459 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use crate::data::env::LambdaRuntimeEnv;
+use crate::data::response::{
+    AWS_CLIENT_CTX, AWS_COG_ID, AWS_DEADLINE_MS, AWS_FUNC_ARN, AWS_REQ_ID, AWS_TRACE_ID,
+};
+
+use crate::testing::mock::mock_env;
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The time until the deadline of an [`Invocation`] that does not set one, counted from when it is served.
+pub static DEFAULT_INVOCATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An event enqueued on a [`MockRuntimeApi`], served by the next invocation endpoint along with its headers.
+#[derive(Clone, Debug)]
+pub struct Invocation {
+    pub request_id: String,
+    pub body: String,
+    /// The value of the `Lambda-Runtime-Deadline-Ms` header, defaults to [`DEFAULT_INVOCATION_TIMEOUT`] after the event is served.
+    pub deadline_ms: Option<u64>,
+    /// Any additional headers sent along with the event.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Invocation {
+    /// Creates an invocation with the given request id and JSON event body.
+    pub fn new(request_id: &str, body: &str) -> Self {
+        Invocation {
+            request_id: request_id.to_string(),
+            body: body.to_string(),
+            deadline_ms: None,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Sets the deadline of the invocation in milliseconds since the unix epoch.
+    pub fn deadline_ms(mut self, deadline_ms: u64) -> Self {
+        self.deadline_ms = Some(deadline_ms);
+        self
+    }
+
+    /// Sets the deadline of the invocation to `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline_ms(epoch_ms() + timeout.as_millis() as u64)
+    }
+
+    /// Sets the `Lambda-Runtime-Invoked-Function-Arn` header.
+    pub fn invoked_function_arn(self, arn: &str) -> Self {
+        self.header(AWS_FUNC_ARN, arn)
+    }
+
+    /// Sets the `Lambda-Runtime-Trace-Id` header.
+    pub fn trace_id(self, trace_id: &str) -> Self {
+        self.header(AWS_TRACE_ID, trace_id)
+    }
+
+    /// Sets the `Lambda-Runtime-Client-Context` header.
+    pub fn client_context(self, client_context: &str) -> Self {
+        self.header(AWS_CLIENT_CTX, client_context)
+    }
+
+    /// Sets the `Lambda-Runtime-Cognito-Identity` header.
+    pub fn cognito_identity(self, cognito_identity: &str) -> Self {
+        self.header(AWS_COG_ID, cognito_identity)
+    }
+
+    /// Adds a custom header to the invocation.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// The runtime API endpoint a [`PostedRequest`] was sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostKind {
+    /// `/runtime/invocation/{request_id}/response`
+    Response,
+    /// `/runtime/invocation/{request_id}/error`
+    Error,
+    /// `/runtime/init/error`
+    InitError,
+}
+
+/// A request posted by a runtime to a [`MockRuntimeApi`].
+#[derive(Clone, Debug)]
+pub struct PostedRequest {
+    pub kind: PostKind,
+    /// The request id in the path of invocation responses and errors.
+    pub request_id: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// The trailers sent after a chunked body, e.g: when streaming a response.
+    pub trailers: Vec<(String, String)>,
+}
+
+impl PostedRequest {
+    /// Returns the value of the first header named `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Returns the value of the first trailer named `name`, compared case-insensitively.
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        find_header(&self.trailers, name)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<Invocation>,
+    posted: Vec<PostedRequest>,
+    /// The number of upcoming requests whose connection is closed without a response.
+    failures: usize,
+    /// The number of requests received, including failed ones.
+    received: usize,
+    shutdown: bool,
+    closed: bool,
+}
+
+/// The state shared between a [`MockRuntimeApi`] and its server threads.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panicking test thread should not hide the recorded requests from other threads
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// An in-process HTTP server implementing the `/runtime/invocation/next`, `/runtime/invocation/{request_id}/response`,
+/// `/runtime/invocation/{request_id}/error` and `/runtime/init/error` endpoints of the
+/// [runtime API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
+///
+/// Events enqueued using [`MockRuntimeApi::enqueue`] are served in order, and requests posted back by the runtime are recorded.
+/// Like the real API, the next invocation endpoint blocks until an event is available.
+///
+/// Once the server is shut down - explicitly or when dropped - pending and future next invocation requests
+/// are answered with a 500 status code, signaling a container error that makes the runtime loop exit.
+pub struct MockRuntimeApi {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl MockRuntimeApi {
+    /// Starts a server listening on a random local port.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed binding mock runtime API");
+        let addr = listener
+            .local_addr()
+            .expect("Failed binding mock runtime API");
+        let shared = Arc::new(Shared::default());
+
+        let acceptor_shared = Arc::clone(&shared);
+        let acceptor = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if acceptor_shared.lock().closed {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let conn_shared = Arc::clone(&acceptor_shared);
+                    std::thread::spawn(move || serve_connection(stream, &conn_shared));
+                }
+            }
+        });
+
+        MockRuntimeApi {
+            addr,
+            shared,
+            acceptor: Some(acceptor),
+        }
+    }
+
+    /// Returns the `host:port` address of the server, as expected in the `AWS_LAMBDA_RUNTIME_API` env-var.
+    pub fn address(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Returns a [`LambdaRuntimeEnv`] pointing to the server, see [`crate::testing::mock::mock_env`].
+    pub fn env(&self) -> LambdaRuntimeEnv {
+        mock_env(&self.address())
+    }
+
+    /// Enqueues an event to be served by the next invocation endpoint.
+    pub fn enqueue(&self, invocation: Invocation) {
+        self.shared.lock().events.push_back(invocation);
+        self.shared.changed.notify_all();
+    }
+
+    /// Returns the number of enqueued events that have not been served yet.
+    pub fn pending(&self) -> usize {
+        self.shared.lock().events.len()
+    }
+
+    /// Returns all the requests posted so far, in the order they were received.
+    pub fn posted(&self) -> Vec<PostedRequest> {
+        self.shared.lock().posted.clone()
+    }
+
+    /// Returns the invocation responses posted so far.
+    pub fn responses(&self) -> Vec<PostedRequest> {
+        self.posted_of(PostKind::Response)
+    }
+
+    /// Returns the invocation errors posted so far.
+    pub fn errors(&self) -> Vec<PostedRequest> {
+        self.posted_of(PostKind::Error)
+    }
+
+    /// Returns the initialization errors posted so far.
+    pub fn init_errors(&self) -> Vec<PostedRequest> {
+        self.posted_of(PostKind::InitError)
+    }
+
+    /// Closes the connection of the next `count` requests without responding, causing a transport error in the runtime.
+    pub fn fail_requests(&self, count: usize) {
+        self.shared.lock().failures += count;
+    }
+
+    /// Returns the number of requests received so far, including failed ones.
+    pub fn received(&self) -> usize {
+        self.shared.lock().received
+    }
+
+    /// Blocks until at least `count` requests were posted or `timeout` has elapsed, and returns the posted requests.
+    pub fn wait_for_posts(&self, count: usize, timeout: Duration) -> Vec<PostedRequest> {
+        let until = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        while state.posted.len() < count {
+            let now = Instant::now();
+            if now >= until {
+                break;
+            }
+            state = match self.shared.changed.wait_timeout(state, until - now) {
+                Ok((state, _)) => state,
+                Err(err) => err.into_inner().0,
+            };
+        }
+        state.posted.clone()
+    }
+
+    /// Shuts the server down, answering pending and future next invocation requests with a container error.
+    pub fn shutdown(&self) {
+        self.shared.lock().shutdown = true;
+        self.shared.changed.notify_all();
+    }
+
+    fn posted_of(&self, kind: PostKind) -> Vec<PostedRequest> {
+        self.shared
+            .lock()
+            .posted
+            .iter()
+            .filter(|req| req.kind == kind)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for MockRuntimeApi {
+    fn drop(&mut self) {
+        self.shutdown();
+        self.shared.lock().closed = true;
+        // Wake the acceptor thread up so it can observe the closed flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+/// A parsed HTTP request.
+struct RawRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    trailers: Vec<(String, String)>,
+}
+
+/// Serves requests on a single connection until it is closed.
+fn serve_connection(stream: TcpStream, shared: &Shared) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    while let Some(req) = read_request(&mut reader) {
+        {
+            let mut state = shared.lock();
+            state.received += 1;
+            if state.failures > 0 {
+                state.failures -= 1;
+                return;
+            }
+        }
+        let close = find_header(&req.headers, "Connection") == Some("close");
+        let (status, headers, body) = route(req, shared);
+
+        let mut resp = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+        for (name, value) in headers {
+            resp.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        resp.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        if writer.write_all(resp.as_bytes()).is_err() || close {
+            return;
+        }
+    }
+}
+
+/// Handles a request according to its method and path, returning the status code, headers and body of the response.
+fn route(req: RawRequest, shared: &Shared) -> (u16, Vec<(String, String)>, String) {
+    let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+    // Paths are prefixed by the API version, e.g: /2018-06-01/runtime/invocation/next
+    let (kind, request_id) = match (req.method.as_str(), &segments[1..]) {
+        ("GET", ["runtime", "invocation", "next"]) => return next_invocation(shared),
+        ("POST", ["runtime", "invocation", id, "response"]) => {
+            (PostKind::Response, Some(id.to_string()))
+        }
+        ("POST", ["runtime", "invocation", id, "error"]) => (PostKind::Error, Some(id.to_string())),
+        ("POST", ["runtime", "init", "error"]) => (PostKind::InitError, None),
+        _ => {
+            let body = r#"{"errorMessage":"Not found","errorType":"NotFound"}"#;
+            return (404, Vec::new(), body.to_string());
+        }
+    };
+
+    shared.lock().posted.push(PostedRequest {
+        kind,
+        request_id,
+        headers: req.headers,
+        body: req.body,
+        trailers: req.trailers,
+    });
+    shared.changed.notify_all();
+
+    (202, Vec::new(), r#"{"status":"OK"}"#.to_string())
+}
+
+/// Blocks until an event is enqueued and serves it, or answers with a container error once the server shuts down.
+fn next_invocation(shared: &Shared) -> (u16, Vec<(String, String)>, String) {
+    let mut state = shared.lock();
+    loop {
+        if state.shutdown {
+            return (500, Vec::new(), String::new());
+        }
+        if let Some(invocation) = state.events.pop_front() {
+            let deadline = invocation
+                .deadline_ms
+                .unwrap_or_else(|| epoch_ms() + DEFAULT_INVOCATION_TIMEOUT.as_millis() as u64);
+            let mut headers = vec![
+                (AWS_REQ_ID.to_string(), invocation.request_id),
+                (AWS_DEADLINE_MS.to_string(), deadline.to_string()),
+            ];
+            headers.extend(invocation.headers);
+            return (200, headers, invocation.body);
+        }
+        state = match shared.changed.wait(state) {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+    }
+}
+
+/// Reads a single request from the connection, returns `None` once the connection is closed or a malformed request is read.
+fn read_request<S: Read>(reader: &mut BufReader<S>) -> Option<RawRequest> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let headers = read_headers(reader)?;
+    let mut body = Vec::new();
+    let mut trailers = Vec::new();
+    if find_header(&headers, "Transfer-Encoding") == Some("chunked") {
+        loop {
+            let size = usize::from_str_radix(read_line(reader)?.trim(), 16).ok()?;
+            if size == 0 {
+                trailers = read_headers(reader)?;
+                break;
+            }
+            let mut chunk = vec![0; size];
+            reader.read_exact(&mut chunk).ok()?;
+            body.extend_from_slice(&chunk);
+            // Skip the CRLF following the chunk
+            read_line(reader)?;
+        }
+    } else if let Some(len) = find_header(&headers, "Content-Length") {
+        let mut buf = vec![0; len.parse().ok()?];
+        reader.read_exact(&mut buf).ok()?;
+        body = buf;
+    }
+
+    Some(RawRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+        trailers,
+    })
+}
+
+/// Reads header lines until an empty line.
+fn read_headers<S: Read>(reader: &mut BufReader<S>) -> Option<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Some(headers);
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+}
+
+/// Reads a line without its line terminator, returns `None` at the end of the stream.
+fn read_line<S: Read>(reader: &mut BufReader<S>) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+fn epoch_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -5,6 +5,11 @@
 use crate::data::response::LambdaAPIResponse;
 use crate::error::Error;
 
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io::Write;
+use std::time::Duration;
+
 /// A generic trait that is used as an abstraction to the HTTP client library (AKA "Backend")
 /// used to interact with the [runtime API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html), and the response type returned by that backend.
 pub trait Transport<T: LambdaAPIResponse>: Default {
@@ -23,4 +28,54 @@ pub trait Transport<T: LambdaAPIResponse>: Default {
         body: Option<&str>,
         headers: Option<(Vec<&str>, Vec<&str>)>,
     ) -> Result<T, Error>;
+    /// Starts an HTTP POST request to the specified `url` with the optional `headers`,
+    /// whose body is sent using chunked transfer encoding by writing to the returned [`ChunkedRequest`].
+    ///
+    /// The default implementation returns an error, for backends that do not support streaming request bodies.
+    fn post_chunked(
+        &self,
+        url: &str,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> Result<Box<dyn ChunkedRequest<T>>, Error> {
+        let _ = (url, headers);
+        Err(Error::Transport {
+            msg: "Chunked requests are not supported by this transport".to_string(),
+            source: None,
+        })
+    }
+    /// Sets the timeout of requests sent by [`Transport::post`] and [`Transport::post_chunked`], e.g: when reporting responses and errors.
+    /// For chunked requests the timeout applies to each write of the body rather than to the whole request.
+    /// Requests sent by [`Transport::get`] are used to wait for the next event and should not time out.
+    ///
+    /// The default implementation ignores the timeout, for backends that do not support it.
+    fn set_post_timeout(&mut self, timeout: Option<Duration>) {
+        let _ = timeout;
+    }
+}
+
+/// A request started by [`Transport::post_chunked`], each write to it is sent as a separate chunk of the request body.
+pub trait ChunkedRequest<T: LambdaAPIResponse>: Write {
+    /// Ends the request body, sending the optional `trailers` after the last chunk, and reads the response.
+    fn finish(self: Box<Self>, trailers: Option<(Vec<&str>, Vec<&str>)>) -> Result<T, Error>;
+}
+
+/// The asynchronous counterpart of [`Transport`], used by [`crate::runtime::async_runtime::AsyncDefaultRuntime`].
+///
+/// The returned futures are required to be [`Send`] so they can be driven by multi-threaded executors.
+#[cfg(feature = "async")]
+pub trait AsyncTransport<T: LambdaAPIResponse>: Default {
+    /// Sends an HTTP GET request to the specified `url` with the optional `body` and `headers`.
+    fn get(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> impl Future<Output = Result<T, Error>> + Send;
+    /// Sends an HTTP POST request to the specified `url` with the optional `body` and `headers`.
+    fn post(
+        &self,
+        url: &str,
+        body: Option<&str>,
+        headers: Option<(Vec<&str>, Vec<&str>)>,
+    ) -> impl Future<Output = Result<T, Error>> + Send;
 }
@@ -0,0 +1,113 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::MockContext;
+use Runtime_Aws_Lambda::data::arn::Arn;
+use Runtime_Aws_Lambda::data::context::LambdaContext;
+use Runtime_Aws_Lambda::error::Error;
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+static FUNCTION_ARN: &str = "arn:aws:lambda:us-east-1:123456789012:function:my-function";
+
+#[test]
+fn parses_unqualified_arns() {
+    let arn: Arn = FUNCTION_ARN.parse().unwrap();
+
+    assert_eq!(
+        arn,
+        Arn {
+            partition: "aws".to_string(),
+            region: "us-east-1".to_string(),
+            account_id: "123456789012".to_string(),
+            function_name: "my-function".to_string(),
+            qualifier: None,
+        }
+    );
+    assert_eq!(arn.version(), None);
+    assert_eq!(arn.alias(), None);
+    assert_eq!(arn.to_string(), FUNCTION_ARN);
+}
+
+#[test]
+fn parses_qualified_arns() {
+    let arn: Arn = "arn:aws-cn:lambda:cn-north-1:123456789012:function:my-function:prod"
+        .parse()
+        .unwrap();
+
+    assert_eq!(arn.partition, "aws-cn");
+    assert_eq!(arn.region, "cn-north-1");
+    assert_eq!(arn.qualifier.as_deref(), Some("prod"));
+    assert_eq!(
+        arn.to_string(),
+        "arn:aws-cn:lambda:cn-north-1:123456789012:function:my-function:prod"
+    );
+}
+
+#[test]
+fn distinguishes_versions_from_aliases() {
+    for (qualifier, version, alias) in [
+        ("42", Some("42"), None),
+        ("$LATEST", Some("$LATEST"), None),
+        ("prod", None, Some("prod")),
+        ("v2", None, Some("v2")),
+    ] {
+        let arn: Arn = format!("{}:{}", FUNCTION_ARN, qualifier).parse().unwrap();
+        assert_eq!(arn.version(), version, "{}", qualifier);
+        assert_eq!(arn.alias(), alias, "{}", qualifier);
+    }
+}
+
+#[test]
+fn rejects_malformed_arns() {
+    for arn in [
+        "",
+        "my-function",
+        "arn:aws:lambda:us-east-1:123456789012:function",
+        "arn:aws:lambda:us-east-1:123456789012:function:my-function:prod:extra",
+        "arn:aws:lambda:us-east-1:123456789012:function:my-function:",
+        "arn:aws:lambda::123456789012:function:my-function",
+        "arn:aws:s3:us-east-1:123456789012:function:my-function",
+        "arn:aws:lambda:us-east-1:123456789012:layer:my-layer",
+    ] {
+        match arn.parse::<Arn>() {
+            Err(Error::InvalidArn(invalid)) => assert_eq!(invalid, arn),
+            other => panic!("Unexpected result for {:?}: {:?}", arn, other),
+        }
+    }
+}
+
+/// Answers with the qualifier the function was invoked with.
+fn route(_: String, context: MockContext) -> Result<String, String> {
+    Ok(context
+        .invoked_qualifier()
+        .unwrap_or_else(|| "unqualified".to_string()))
+}
+
+#[test]
+fn exposes_the_invoked_qualifier() {
+    let transport = MockTransport::new();
+    for (id, arn) in [
+        format!("{}:canary", FUNCTION_ARN),
+        format!("{}:7", FUNCTION_ARN),
+        FUNCTION_ARN.to_string(),
+        "not an arn".to_string(),
+    ]
+    .iter()
+    .enumerate()
+    {
+        transport.push_next(MockResponse::event(&id.to_string(), "\"\"").invoked_function_arn(arn));
+    }
+    let mut runtime = common::runtime(&transport, || Ok(route));
+
+    runtime.run_n(4).unwrap();
+
+    transport.assert_response("0", "\"canary\"");
+    transport.assert_response("1", "\"7\"");
+    transport.assert_response("2", "\"unqualified\"");
+    // Malformed ARNs are treated as unqualified, `invoked_arn` reports the error
+    transport.assert_response("3", "\"unqualified\"");
+}
@@ -0,0 +1,235 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{block_on, AsyncMockContext, AsyncMockRuntime, ExitCodes, RUNTIME_API};
+use Runtime_Aws_Lambda::data::context::LambdaContext;
+use Runtime_Aws_Lambda::data::env::RuntimeEnvVars;
+use Runtime_Aws_Lambda::error::{HANDLER_ERR_TYPE, INIT_ERR_TYPE, SERIALIZATION_ERR_TYPE};
+use Runtime_Aws_Lambda::runtime::async_runtime::AsyncLambdaRuntime;
+use Runtime_Aws_Lambda::runtime::retry::RetryPolicy;
+use Runtime_Aws_Lambda::runtime::{CONTAINER_ERR_EXIT_CODE, INIT_ERR_EXIT_CODE};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+use Runtime_Aws_Lambda::LAMBDA_VER;
+
+use std::time::{Duration, Instant};
+
+/// Echoes events along with their request id, refusing empty events.
+fn echo_runtime(transport: &MockTransport) -> AsyncMockRuntime {
+    AsyncMockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        || async {
+            Ok(|event: String, context: AsyncMockContext| async move {
+                if event.is_empty() {
+                    return Err("Nothing to echo".to_string());
+                }
+                Ok(format!("{}: {}", context.aws_request_id().unwrap(), event))
+            })
+        },
+    )
+}
+
+/// Runs `runtime` until the scripted events run out, returning the exit codes it passed to its exit hook.
+fn run_to_end(runtime: &mut AsyncMockRuntime) -> Vec<i32> {
+    let exit_codes = ExitCodes::default();
+    runtime.set_exit_hook(exit_codes.hook());
+    block_on(runtime.run());
+    exit_codes.get()
+}
+
+#[test]
+fn responds_with_the_output_of_async_handlers() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", r#""hello""#)
+        .push_event("b", r#""world""#);
+
+    let exit_codes = run_to_end(&mut echo_runtime(&transport));
+
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+    transport.assert_response("a", r#""a: hello""#);
+    transport.assert_response("b", r#""b: world""#);
+    transport.assert_no_errors();
+}
+
+#[test]
+fn runs_the_runtime_loop_on_another_thread() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+    let exit_codes = ExitCodes::default();
+    let mut runtime = echo_runtime(&transport);
+    runtime.set_exit_hook(exit_codes.hook());
+
+    // The future of the runtime loop is `Send`, so it may be created here and polled on another thread
+    let run = async move { runtime.run().await };
+    std::thread::spawn(move || block_on(run)).join().unwrap();
+
+    assert_eq!(exit_codes.get(), vec![CONTAINER_ERR_EXIT_CODE]);
+    transport.assert_response("a", r#""a: hello""#);
+}
+
+#[test]
+fn passes_the_shared_state_to_async_handlers() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+    let mut runtime = AsyncMockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        || async {
+            Ok(|event: String, context: AsyncMockContext| async move {
+                let region = context.shared_state().and_then(|state| state.get::<&str>());
+                Ok(format!("{} from {}", event, region.ok_or("No region")?))
+            })
+        },
+    );
+    runtime.shared_state_mut().insert("eu-west-1");
+
+    run_to_end(&mut runtime);
+
+    transport.assert_response("a", r#""hello from eu-west-1""#);
+}
+
+#[test]
+fn reports_async_handler_errors() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""""#);
+
+    run_to_end(&mut echo_runtime(&transport));
+
+    transport.assert_error("a", HANDLER_ERR_TYPE);
+    assert!(transport.responses().is_empty());
+}
+
+#[test]
+fn reports_async_initialization_errors_and_exits() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+    let mut runtime = AsyncMockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        || async {
+            Err::<fn(String, AsyncMockContext) -> std::future::Ready<Result<String, String>>, _>(
+                "Echo chamber unavailable".to_string(),
+            )
+        },
+    );
+
+    let exit_codes = run_to_end(&mut runtime);
+
+    assert_eq!(exit_codes, vec![INIT_ERR_EXIT_CODE]);
+    transport.assert_init_error(INIT_ERR_TYPE);
+    assert_eq!(transport.calls().len(), 1);
+}
+
+#[test]
+fn retries_failed_calls_until_they_succeed() {
+    let transport = MockTransport::new();
+    transport
+        .push_next_err("Connection reset")
+        .push_next_err("Connection reset")
+        .push_event("a", r#""hello""#);
+    transport.push_post_err("Connection reset");
+    let mut runtime = echo_runtime(&transport);
+    runtime.set_retry_policy(
+        RetryPolicy::default().backoff(Duration::from_millis(1), Duration::from_millis(5)),
+    );
+
+    let exit_codes = run_to_end(&mut runtime);
+
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+    transport.assert_response("a", r#""a: hello""#);
+    assert_eq!(transport.responses().len(), 2);
+    // Two failed attempts, the event, two posts of its response and the container error ending the loop
+    assert_eq!(transport.calls().len(), 6);
+}
+
+#[test]
+fn backs_off_failed_cycles() {
+    let transport = MockTransport::new();
+    for _ in 0..3 {
+        transport.push_next(MockResponse::client_error(
+            400,
+            "InvalidRequest",
+            "Bad request",
+        ));
+    }
+    let mut runtime = echo_runtime(&transport);
+    runtime.set_retry_policy(
+        RetryPolicy::default()
+            .backoff(Duration::from_millis(20), Duration::from_secs(1))
+            .jitter(false),
+    );
+
+    let started = Instant::now();
+    let exit_codes = run_to_end(&mut runtime);
+
+    // Client errors are not retried, but each failed cycle backs off: 20ms, 40ms and 80ms
+    assert!(started.elapsed() >= Duration::from_millis(140));
+    assert_eq!(transport.calls().len(), 4);
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+}
+
+/// A response that always fails to serialize.
+struct Unserializable;
+
+impl serde::Serialize for Unserializable {
+    fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("Not serializable"))
+    }
+}
+
+#[test]
+fn reports_responses_that_fail_to_serialize() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+    let mut runtime = AsyncMockRuntime::<String, Unserializable>::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        || async { Ok(|_: String, _: AsyncMockContext| async { Ok(Unserializable) }) },
+    );
+
+    let exit_codes = ExitCodes::default();
+    runtime.set_exit_hook(exit_codes.hook());
+    block_on(runtime.run());
+
+    transport.assert_error("a", SERIALIZATION_ERR_TYPE);
+    assert!(transport.responses().is_empty());
+    assert_eq!(exit_codes.get(), vec![CONTAINER_ERR_EXIT_CODE]);
+}
+
+/// Answers with the trace id of the env-vars instance.
+async fn trace(_: String, context: AsyncMockContext) -> Result<String, String> {
+    Ok(format!("{:?}", context.env_vars.get_trace_id()))
+}
+
+#[test]
+fn clears_the_trace_id_of_the_previous_invocation() {
+    let transport = MockTransport::new();
+    transport
+        .push_next(
+            MockResponse::event("a", "\"\"").trace_id("Root=1-5759e988-bd862e3fe1be46a994272793"),
+        )
+        .push_event("b", "\"\"");
+    let mut runtime = AsyncMockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        || async { Ok(trace) },
+    );
+
+    run_to_end(&mut runtime);
+
+    transport.assert_response(
+        "a",
+        r#""Some(\"Root=1-5759e988-bd862e3fe1be46a994272793\")""#,
+    );
+    transport.assert_response("b", "\"None\"");
+    assert_eq!(runtime.get_env().get_trace_id(), None);
+}
@@ -0,0 +1,117 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{ExitCodes, MockHandler, MockRuntime, RUNTIME_API};
+use Runtime_Aws_Lambda::error::{Error, SERIALIZATION_ERR_TYPE};
+use Runtime_Aws_Lambda::runtime::{
+    json_serializer, InvocationOutcome, LambdaRuntime, CONTAINER_ERR_EXIT_CODE,
+};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockTransport};
+
+fn initialize() -> Result<MockHandler, String> {
+    Ok(Box::new(|event, _| Ok(event.to_uppercase())))
+}
+
+#[allow(clippy::ptr_arg)]
+fn plain_text(response: &String) -> Result<String, Error> {
+    Ok(response.clone())
+}
+
+#[allow(clippy::ptr_arg)]
+fn refuse_empty(response: &String) -> Result<String, Error> {
+    match response.is_empty() {
+        true => Err(Error::Env("Nothing to serialize".to_string())),
+        false => json_serializer(response),
+    }
+}
+
+#[test]
+fn builds_with_explicit_configuration() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+
+    let mut runtime = MockRuntime::builder(initialize)
+        .api_endpoint(RUNTIME_API)
+        .version("/2018-06-01")
+        .env(mock_env("unused:1"))
+        .transport(transport.clone())
+        .serializer(plain_text)
+        .build()
+        .unwrap();
+    runtime.run_once().unwrap();
+
+    let calls = transport.calls();
+    assert_eq!(
+        calls[0].url,
+        "http://127.0.0.1:9001/2018-06-01/runtime/invocation/next"
+    );
+    let responses = transport.responses();
+    assert_eq!(responses[0].request_id(), Some("a"));
+    assert_eq!(responses[0].body.as_deref(), Some("HELLO"));
+}
+
+#[test]
+fn fails_without_a_runtime_api_address() {
+    let mut env = mock_env("unused:1");
+    env.runtime_api = None;
+
+    let result = MockRuntime::builder(initialize).env(env).build();
+
+    match result {
+        Err(Error::Env(msg)) => assert!(msg.contains("AWS_LAMBDA_RUNTIME_API")),
+        Err(err) => panic!("Unexpected error: {}", err),
+        Ok(_) => panic!("The runtime was built without an address"),
+    }
+}
+
+#[test]
+fn prefers_the_explicit_endpoint_over_the_env_vars() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#""hello""#);
+    let mut env = mock_env("unused:1");
+    env.runtime_api = None;
+
+    let mut runtime = MockRuntime::builder(initialize)
+        .api_endpoint(RUNTIME_API)
+        .env(env)
+        .transport(transport.clone())
+        .build()
+        .unwrap();
+    runtime.run_once().unwrap();
+
+    assert!(transport.calls()[0]
+        .url
+        .starts_with(&format!("http://{}/", RUNTIME_API)));
+}
+
+#[test]
+fn reports_responses_that_fail_to_serialize() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", r#""""#)
+        .push_event("b", r#""hello""#);
+    let exit_codes = ExitCodes::default();
+
+    let mut runtime = MockRuntime::builder(initialize)
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .serializer(refuse_empty)
+        .exit_hook(exit_codes.hook())
+        .build()
+        .unwrap();
+    let outcome = runtime.run_once().unwrap();
+    runtime.run();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::SerializationError {
+            request_id: "a".to_string()
+        }
+    );
+    transport.assert_error("a", SERIALIZATION_ERR_TYPE);
+    transport.assert_response("b", r#""HELLO""#);
+    assert_eq!(exit_codes.get(), vec![CONTAINER_ERR_EXIT_CODE]);
+}
@@ -0,0 +1,172 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{block_on, AsyncMockContext, AsyncMockRuntime, ExitCodes, MockContext, MockHandler};
+use common::{MockRuntime, RUNTIME_API};
+use Runtime_Aws_Lambda::data::context::LambdaContext;
+use Runtime_Aws_Lambda::error::Error;
+use Runtime_Aws_Lambda::runtime::async_runtime::AsyncLambdaRuntime;
+use Runtime_Aws_Lambda::runtime::cancel::CancellationToken;
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+use Runtime_Aws_Lambda::LAMBDA_VER;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Keeps the token of the "return" event, to check it after the invocation ended.
+type TokenSlot = Arc<Mutex<Option<CancellationToken>>>;
+
+/// The margin before the deadline at which the tokens are cancelled, leaving handlers ample time to observe it.
+const MARGIN: Duration = Duration::from_millis(500);
+
+/// Scripts an event whose deadline is a second away.
+fn push_event(transport: &MockTransport, event: &str) {
+    transport.push_next(
+        MockResponse::event("a", &format!("\"{}\"", event)).timeout(Duration::from_secs(1)),
+    );
+}
+
+/// Checks that a token was cancelled before the deadline, given the remaining time once it was.
+fn cancelled_in_time(remaining: Result<Duration, Error>) -> Result<String, String> {
+    match remaining {
+        Ok(_) => Ok("cancelled".to_string()),
+        Err(_) => Err("Cancelled after the deadline".to_string()),
+    }
+}
+
+/// Waits for the token for the "wait" event and returns immediately otherwise, keeping the token in `returned`.
+fn cancellable_runtime(
+    transport: &MockTransport,
+    event: &str,
+    margin: Option<Duration>,
+    returned: &TokenSlot,
+) -> MockRuntime {
+    push_event(transport, event);
+    let returned = returned.clone();
+    let handler: MockHandler = Box::new(move |event, context: MockContext| {
+        let token = context.cancellation.ok_or("No cancellation token")?;
+        match event.as_str() {
+            "wait" if token.is_cancelled() => Err("Cancelled on arrival".to_string()),
+            "wait" if token.wait_timeout(Duration::from_secs(5)) => {
+                cancelled_in_time(context.get_remaining_time_ms())
+            }
+            "wait" => Err("The token was not cancelled".to_string()),
+            _ => {
+                *returned.lock().unwrap() = Some(token.clone());
+                Ok(event)
+            }
+        }
+    });
+    let mut runtime = common::runtime(transport, move || Ok(handler));
+    runtime.set_cancellation_margin(margin);
+    runtime
+}
+
+/// The async counterpart of [`cancellable_runtime`], ending its loop once the event was served.
+fn async_cancellable_runtime(
+    transport: &MockTransport,
+    event: &str,
+    returned: &TokenSlot,
+) -> AsyncMockRuntime {
+    push_event(transport, event);
+    let returned = returned.clone();
+    let mut runtime = AsyncMockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        move || async move {
+            Ok(move |event: String, context: AsyncMockContext| {
+                let returned = returned.clone();
+                async move {
+                    let token = context
+                        .cancellation
+                        .clone()
+                        .ok_or("No cancellation token")?;
+                    match event.as_str() {
+                        "wait" if token.is_cancelled() => Err("Cancelled on arrival".to_string()),
+                        "wait" => {
+                            token.cancelled().await;
+                            cancelled_in_time(context.get_remaining_time_ms())
+                        }
+                        _ => {
+                            *returned.lock().unwrap() = Some(token);
+                            Ok(event)
+                        }
+                    }
+                }
+            })
+        },
+    );
+    runtime.set_cancellation_margin(Some(MARGIN));
+    runtime.set_exit_hook(ExitCodes::default().hook());
+    runtime
+}
+
+#[test]
+fn cancels_the_token_before_the_deadline() {
+    let transport = MockTransport::new();
+    let mut runtime = cancellable_runtime(&transport, "wait", Some(MARGIN), &TokenSlot::default());
+
+    runtime.run_once().unwrap();
+
+    transport.assert_response("a", r#""cancelled""#);
+    transport.assert_no_errors();
+}
+
+#[test]
+fn does_not_cancel_the_token_once_the_handler_returned() {
+    let transport = MockTransport::new();
+    let returned = TokenSlot::default();
+    let mut runtime = cancellable_runtime(&transport, "return", Some(MARGIN), &returned);
+
+    runtime.run_once().unwrap();
+    std::thread::sleep(Duration::from_millis(600));
+
+    transport.assert_response("a", r#""return""#);
+    let token = returned.lock().unwrap().take().unwrap();
+    assert!(!token.is_cancelled());
+}
+
+#[test]
+fn passes_no_token_without_a_margin() {
+    let transport = MockTransport::new();
+    let mut runtime = cancellable_runtime(&transport, "wait", None, &TokenSlot::default());
+
+    runtime.run_once().unwrap();
+
+    let errors = transport.errors();
+    assert!(errors[0]
+        .body
+        .as_deref()
+        .unwrap()
+        .contains("No cancellation token"));
+}
+
+#[test]
+fn async_cancels_the_token_before_the_deadline() {
+    let transport = MockTransport::new();
+    let mut runtime = async_cancellable_runtime(&transport, "wait", &TokenSlot::default());
+
+    block_on(runtime.run());
+
+    transport.assert_response("a", r#""cancelled""#);
+    transport.assert_no_errors();
+}
+
+#[test]
+fn async_does_not_cancel_the_token_once_the_handler_returned() {
+    let transport = MockTransport::new();
+    let returned = TokenSlot::default();
+    let mut runtime = async_cancellable_runtime(&transport, "return", &returned);
+
+    block_on(runtime.run());
+    std::thread::sleep(Duration::from_millis(600));
+
+    transport.assert_response("a", r#""return""#);
+    let token = returned.lock().unwrap().take().unwrap();
+    assert!(!token.is_cancelled());
+}
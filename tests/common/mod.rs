@@ -0,0 +1,109 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+//! Fixtures shared by the integration tests, each test crate uses a subset of them.
+
+#![allow(dead_code)]
+
+use Runtime_Aws_Lambda::data::context::{ArcLambdaContext, RefLambdaContext};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::runtime::async_runtime::AsyncDefaultRuntime;
+use Runtime_Aws_Lambda::runtime::handler::Handler;
+use Runtime_Aws_Lambda::runtime::{DefaultRuntime, EventHandler, LambdaRuntime};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+use Runtime_Aws_Lambda::LAMBDA_VER;
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake};
+
+/// The runtime API address set in the env-vars of the runtimes under test, which the mock transport never connects to.
+pub static RUNTIME_API: &str = "127.0.0.1:9001";
+
+/// A runtime driven by a [`MockTransport`], with `String` errors.
+pub type MockRuntime<IN = String, OUT = String> =
+    DefaultRuntime<MockResponse, MockTransport, LambdaRuntimeEnv, IN, OUT, String>;
+
+/// The event handler of a [`MockRuntime`].
+pub type MockHandler<IN = String, OUT = String> =
+    EventHandler<IN, LambdaRuntimeEnv, MockResponse, OUT, String>;
+
+/// The context object passed to the event handler of a [`MockRuntime`].
+pub type MockContext<'a> = RefLambdaContext<'a, LambdaRuntimeEnv, MockResponse>;
+
+/// An async runtime driven by a [`MockTransport`], with `String` errors.
+pub type AsyncMockRuntime<IN = String, OUT = String> =
+    AsyncDefaultRuntime<MockResponse, MockTransport, LambdaRuntimeEnv, IN, OUT, String>;
+
+/// The context object passed to the event handler of an [`AsyncMockRuntime`].
+pub type AsyncMockContext = ArcLambdaContext<LambdaRuntimeEnv, MockResponse>;
+
+/// Creates a runtime sending its requests through a clone of `transport`.
+pub fn runtime<IN, OUT, I, H>(transport: &MockTransport, initializer: I) -> MockRuntime<IN, OUT>
+where
+    IN: serde::de::DeserializeOwned,
+    OUT: serde::Serialize,
+    I: FnOnce() -> Result<H, String> + Send + 'static,
+    H: Handler<IN, LambdaRuntimeEnv, MockResponse, OUT, String> + Send + 'static,
+{
+    MockRuntime::from_parts(
+        LAMBDA_VER,
+        mock_env(RUNTIME_API),
+        transport.clone(),
+        initializer,
+    )
+}
+
+/// Runs `runtime` until the scripted events run out, returning the exit codes it passed to its exit hook.
+/// The mock transport answers with a container error once its events run out, which ends the runtime loop.
+pub fn run_to_end<IN, OUT>(runtime: &mut MockRuntime<IN, OUT>) -> Vec<i32>
+where
+    IN: serde::de::DeserializeOwned,
+    OUT: serde::Serialize,
+{
+    let exit_codes = ExitCodes::default();
+    runtime.set_exit_hook(exit_codes.hook());
+    runtime.run();
+    exit_codes.get()
+}
+
+/// Records the exit codes passed to the exit hooks it creates.
+#[derive(Clone, Default)]
+pub struct ExitCodes(Arc<Mutex<Vec<i32>>>);
+
+impl ExitCodes {
+    /// An exit hook recording its exit code instead of terminating the process.
+    pub fn hook(&self) -> impl FnMut(i32) + Send + 'static {
+        let codes = self.0.clone();
+        move |code| codes.lock().unwrap().push(code)
+    }
+
+    /// The exit codes recorded so far.
+    pub fn get(&self) -> Vec<i32> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Wakes the thread blocked in [`block_on`].
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drives `future` to completion on the current thread, as the async runtime does not depend on a specific executor.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{MockContext, MockRuntime, RUNTIME_API};
+use Runtime_Aws_Lambda::data::context::{ContextFactory, LambdaContext, OwnedLambdaContext};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::runtime::builder::RuntimeBuilder;
+use Runtime_Aws_Lambda::runtime::layer::{Layer, Next};
+use Runtime_Aws_Lambda::runtime::{DefaultRuntime, LambdaRuntime};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+
+use std::time::Duration;
+
+type TenantRuntime = DefaultRuntime<
+    MockResponse,
+    MockTransport,
+    LambdaRuntimeEnv,
+    String,
+    String,
+    String,
+    TenantFactory,
+>;
+
+/// A context carrying the tenant of the invocation on top of the context created by the runtime.
+struct TenantContext<'a> {
+    base: MockContext<'a>,
+    tenant: String,
+}
+
+impl<'a> LambdaContext for TenantContext<'a> {
+    fn get_deadline(&self) -> Option<Duration> {
+        self.base.get_deadline()
+    }
+
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self.base.invoked_function_arn()
+    }
+
+    fn aws_request_id(&self) -> Option<&str> {
+        self.base.aws_request_id()
+    }
+
+    fn function_name(&self) -> Option<&str> {
+        self.base.function_name()
+    }
+
+    fn function_version(&self) -> Option<&str> {
+        self.base.function_version()
+    }
+
+    fn memory_limit_in_mb(&self) -> Option<usize> {
+        self.base.memory_limit_in_mb()
+    }
+
+    fn log_group_name(&self) -> Option<&str> {
+        self.base.log_group_name()
+    }
+
+    fn log_stream_name(&self) -> Option<&str> {
+        self.base.log_stream_name()
+    }
+
+    fn cognito_identity(&self) -> Option<&str> {
+        self.base.cognito_identity()
+    }
+
+    fn client_context(&self) -> Option<&str> {
+        self.base.client_context()
+    }
+}
+
+/// Reads the tenant from the custom values of the client context, falling back to `default_tenant`.
+struct TenantFactory {
+    default_tenant: &'static str,
+}
+
+impl ContextFactory<LambdaRuntimeEnv, MockResponse> for TenantFactory {
+    type Context<'a> = TenantContext<'a>;
+
+    fn create<'a>(&self, base: MockContext<'a>) -> Self::Context<'a> {
+        let tenant = base
+            .parse_client_context()
+            .ok()
+            .flatten()
+            .and_then(|client_context| {
+                let tenant = client_context.custom.get("tenant")?;
+                tenant.as_str().map(str::to_string)
+            })
+            .unwrap_or_else(|| self.default_tenant.to_string());
+        TenantContext { base, tenant }
+    }
+}
+
+/// Answers with the tenant and the request id of the invocation.
+fn greet(_: String, context: TenantContext<'_>) -> Result<String, String> {
+    Ok(format!(
+        "{} {}",
+        context.tenant,
+        context.aws_request_id().unwrap_or_default()
+    ))
+}
+
+/// Rejects the invocations of `tenant`, showing layers receive the custom context too.
+struct Block {
+    tenant: &'static str,
+}
+
+impl Layer<String, LambdaRuntimeEnv, MockResponse, String, String, TenantFactory> for Block {
+    fn call(
+        &mut self,
+        event: String,
+        context: TenantContext<'_>,
+        next: Next<String, LambdaRuntimeEnv, MockResponse, String, String, TenantFactory>,
+    ) -> Result<String, String> {
+        match context.tenant == self.tenant {
+            true => Err(format!("Tenant {} is blocked", context.tenant)),
+            false => next.run(event, context),
+        }
+    }
+}
+
+fn transport() -> MockTransport {
+    let transport = MockTransport::new();
+    transport
+        .push_next(
+            MockResponse::event("a", "\"\"").client_context(r#"{"custom": {"tenant": "acme"}}"#),
+        )
+        .push_event("b", "\"\"");
+    transport
+}
+
+#[test]
+fn passes_the_context_created_by_the_factory() {
+    let transport = transport();
+    let mut runtime: TenantRuntime = RuntimeBuilder::with_context_factory(
+        TenantFactory {
+            default_tenant: "public",
+        },
+        || Ok(greet),
+    )
+    .env(mock_env(RUNTIME_API))
+    .transport(transport.clone())
+    .build()
+    .unwrap();
+
+    runtime.run_n(2).unwrap();
+
+    transport.assert_response("a", "\"acme a\"");
+    transport.assert_response("b", "\"public b\"");
+}
+
+#[test]
+fn passes_the_context_created_by_the_factory_to_layers() {
+    let transport = transport();
+    let mut runtime: TenantRuntime = RuntimeBuilder::with_context_factory(
+        TenantFactory {
+            default_tenant: "public",
+        },
+        || Ok(greet),
+    )
+    .env(mock_env(RUNTIME_API))
+    .transport(transport.clone())
+    .layer(Block { tenant: "public" })
+    .build()
+    .unwrap();
+
+    runtime.run_n(2).unwrap();
+
+    transport.assert_response("a", "\"acme a\"");
+    let errors = transport.errors();
+    assert_eq!(errors[0].request_id(), Some("b"));
+    assert!(errors[0]
+        .body
+        .as_deref()
+        .unwrap()
+        .contains("Tenant public is blocked"));
+}
+
+/// Fails to compile unless `T` can be moved to and shared between threads.
+fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+#[test]
+fn owned_contexts_are_send_and_sync() {
+    assert_send_sync::<OwnedLambdaContext>();
+}
+
+/// Describes the invocation from a worker thread, using an owned snapshot of the context.
+fn describe_on_worker(_: String, context: MockContext) -> Result<String, String> {
+    let owned = context.into_owned();
+    std::thread::spawn(move || {
+        let region = owned.shared_state().and_then(|state| state.get::<&str>());
+        format!(
+            "{} {} {} {:?} {}",
+            owned.aws_request_id().unwrap_or_default(),
+            owned
+                .invoked_arn()
+                .ok()
+                .flatten()
+                .map(|arn| arn.function_name)
+                .unwrap_or_default(),
+            owned.get_remaining_time_ms().is_ok(),
+            region,
+            owned.cancellation().is_some(),
+        )
+    })
+    .join()
+    .map_err(|_| "The worker panicked".to_string())
+}
+
+#[test]
+fn owned_contexts_keep_the_token_and_the_shared_state() {
+    let transport = MockTransport::new();
+    transport.push_next(
+        MockResponse::event("a", "\"\"")
+            .invoked_function_arn("arn:aws:lambda:us-east-1:123456789012:function:snapshot"),
+    );
+    let mut runtime = MockRuntime::builder(|| Ok(describe_on_worker))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .shared_state("eu-west-1")
+        .build()
+        .unwrap();
+    runtime.set_cancellation_margin(Some(Duration::from_millis(100)));
+
+    runtime.run_once().unwrap();
+
+    transport.assert_response("a", "\"a snapshot true Some(\\\"eu-west-1\\\") true\"");
+}
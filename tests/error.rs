@@ -0,0 +1,138 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use Runtime_Aws_Lambda::error::{
+    Error, ErrorRequest, ErrorResponse, LambdaError, HANDLER_ERR_TYPE,
+};
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+struct OutOfStock(&'static str);
+
+impl Display for OutOfStock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is out of stock", self.0)
+    }
+}
+
+impl std::error::Error for OutOfStock {}
+
+impl LambdaError for OutOfStock {
+    fn error_type(&self) -> Option<&str> {
+        Some("Shop.OutOfStock")
+    }
+}
+
+#[derive(Debug)]
+struct CheckoutFailed(OutOfStock);
+
+impl Display for CheckoutFailed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Checkout failed")
+    }
+}
+
+impl std::error::Error for CheckoutFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[test]
+fn serializes_in_camel_case() {
+    let error_req = ErrorRequest::new("Shop.Closed", "Closed for the day");
+
+    let json: serde_json::Value = serde_json::to_value(&error_req).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "errorMessage": "Closed for the day",
+            "errorType": "Shop.Closed",
+            "stackTrace": [],
+        })
+    );
+}
+
+#[test]
+fn uses_the_default_type_unless_overridden() {
+    let plain = ErrorRequest::from_error(&"Closed for the day", HANDLER_ERR_TYPE);
+    assert_eq!(plain.error_type, HANDLER_ERR_TYPE);
+    assert_eq!(plain.error_message, "Closed for the day");
+
+    let custom = ErrorRequest::from_error(&OutOfStock("apple"), HANDLER_ERR_TYPE);
+    assert_eq!(custom.error_type, "Shop.OutOfStock");
+    assert_eq!(custom.error_message, "apple is out of stock");
+}
+
+#[test]
+fn reports_error_sources_as_the_stack_trace() {
+    let err: Box<dyn std::error::Error + Send + Sync> =
+        Box::new(CheckoutFailed(OutOfStock("apple")));
+
+    let error_req = ErrorRequest::from_error(&err, HANDLER_ERR_TYPE);
+
+    assert_eq!(error_req.error_message, "Checkout failed");
+    assert_eq!(error_req.stack_trace, vec!["apple is out of stock"]);
+}
+
+#[test]
+fn distinguishes_container_errors() {
+    assert!(Error::Container { status: 500 }.is_container_err());
+    assert!(!Error::Http {
+        status: 400,
+        body: None
+    }
+    .is_container_err());
+    assert!(!Error::transport("Failed sending request", "Connection refused").is_container_err());
+}
+
+#[test]
+fn displays_client_error_responses() {
+    let response: ErrorResponse = serde_json::from_str(
+        r#"{"errorMessage": "Invalid request id", "errorType": "InvalidRequestID"}"#,
+    )
+    .unwrap();
+    let err = Error::Http {
+        status: 400,
+        body: Some(response),
+    };
+
+    assert_eq!(
+        err.to_string(),
+        "Client error (400). ErrorResponse: InvalidRequestID: Invalid request id"
+    );
+}
+
+#[test]
+fn chains_transport_errors() {
+    let err = Error::transport("Failed sending request", OutOfStock("apple"));
+
+    assert_eq!(
+        err.to_string(),
+        "Transport error. Failed sending request: apple is out of stock"
+    );
+    assert_eq!(err.source().unwrap().to_string(), "apple is out of stock");
+}
+
+#[test]
+fn chains_serialization_errors() {
+    let json_err = serde_json::from_str::<u32>("apple").unwrap_err();
+    let json_msg = json_err.to_string();
+
+    let err = Error::from(json_err);
+
+    assert!(matches!(err, Error::Serialization(_)));
+    assert_eq!(err.source().unwrap().to_string(), json_msg);
+}
+
+#[test]
+fn has_no_source_for_runtime_errors() {
+    let err = Error::MissingHeader("Lambda-Runtime-Deadline-Ms");
+
+    assert_eq!(err.to_string(), "Missing Lambda-Runtime-Deadline-Ms header");
+    assert!(err.source().is_none());
+}
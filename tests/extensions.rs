@@ -0,0 +1,121 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{MockContext, MockRuntime, RUNTIME_API};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::data::extensions::{Extensions, SharedState};
+use Runtime_Aws_Lambda::runtime::handler::Handler;
+use Runtime_Aws_Lambda::runtime::layer::{Layer, Next};
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+struct Claims(String);
+
+#[derive(Debug, PartialEq)]
+struct Attempts(u32);
+
+#[test]
+fn extensions_hold_a_value_of_each_type() {
+    let extensions = Extensions::new();
+    assert!(extensions.is_empty());
+
+    assert_eq!(extensions.insert(Claims("alice".to_string())), None);
+    assert_eq!(extensions.insert(Attempts(1)), None);
+    assert_eq!(extensions.len(), 2);
+    assert_eq!(
+        *extensions.get::<Claims>().unwrap(),
+        Claims("alice".to_string())
+    );
+
+    extensions.get_mut::<Attempts>().unwrap().0 += 1;
+    assert_eq!(*extensions.get::<Attempts>().unwrap(), Attempts(2));
+    assert_eq!(
+        extensions.insert(Claims("bob".to_string())),
+        Some(Claims("alice".to_string()))
+    );
+    assert_eq!(
+        extensions.remove::<Claims>(),
+        Some(Claims("bob".to_string()))
+    );
+    assert!(!extensions.contains::<Claims>());
+    assert!(extensions.get::<String>().is_none());
+}
+
+#[test]
+fn shared_state_hands_out_shared_pointers() {
+    let mut shared_state = SharedState::new();
+    shared_state.insert(Attempts(3));
+
+    let attempts: Arc<Attempts> = shared_state.get_arc().unwrap();
+    assert_eq!(*attempts, Attempts(3));
+    assert_eq!(shared_state.get::<Attempts>(), Some(&Attempts(3)));
+    assert!(!shared_state.contains::<Claims>());
+    assert_eq!(shared_state.len(), 1);
+}
+
+/// Parses the claims of non-empty events into the extensions of the invocation.
+struct Authenticate;
+
+impl Layer<String, LambdaRuntimeEnv, MockResponse, String, String> for Authenticate {
+    fn call(
+        &mut self,
+        event: String,
+        context: MockContext,
+        next: Next<String, LambdaRuntimeEnv, MockResponse, String, String>,
+    ) -> Result<String, String> {
+        if !event.is_empty() {
+            context.extensions.insert(Claims(event.to_uppercase()));
+        }
+        next.run(event, context)
+    }
+}
+
+/// A connection pool, created while initializing the handler.
+struct Pool(&'static str);
+
+/// Answers with the claims set by the layer, the pool created during init and the region set while building the runtime.
+struct Greeter;
+
+impl Handler<String, LambdaRuntimeEnv, MockResponse, String, String> for Greeter {
+    fn init(&mut self, shared_state: &mut SharedState) -> Result<(), String> {
+        shared_state.insert(Pool("db"));
+        Ok(())
+    }
+
+    fn handle(&mut self, _: String, context: MockContext) -> Result<String, String> {
+        let user = match context.extensions.get::<Claims>() {
+            Some(claims) => claims.0.clone(),
+            None => "anonymous".to_string(),
+        };
+        let pool = context.shared_state.get::<Pool>().ok_or("No pool")?;
+        let region = context.shared_state.get::<&str>().ok_or("No region")?;
+        Ok(format!("{} {} {}", user, pool.0, region))
+    }
+}
+
+#[test]
+fn passes_extensions_and_shared_state_to_the_handler() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", "\"alice\"")
+        .push_event("b", "\"\"");
+    let mut runtime = MockRuntime::builder(|| Ok(Greeter))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .shared_state("eu-west-1")
+        .layer(Authenticate)
+        .build()
+        .unwrap();
+
+    runtime.run_n(2).unwrap();
+
+    transport.assert_response("a", "\"ALICE db eu-west-1\"");
+    // The extensions are created empty for each invocation
+    transport.assert_response("b", "\"anonymous db eu-west-1\"");
+}
@@ -0,0 +1,149 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{run_to_end, runtime, MockContext, MockRuntime};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::data::extensions::SharedState;
+use Runtime_Aws_Lambda::error::{Error, ErrorRequest, INIT_ERR_TYPE};
+use Runtime_Aws_Lambda::runtime::handler::Handler;
+use Runtime_Aws_Lambda::runtime::{LambdaRuntime, INIT_ERR_EXIT_CODE};
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+use std::sync::{Arc, Mutex};
+
+/// Records every lifecycle call, failing to initialize if `fail_init` is set and to handle the "fail" event.
+struct Recorder {
+    calls: Arc<Mutex<Vec<String>>>,
+    fail_init: bool,
+}
+
+impl Recorder {
+    fn record(&self, call: &str) {
+        self.calls.lock().unwrap().push(call.to_string());
+    }
+}
+
+impl Handler<String, LambdaRuntimeEnv, MockResponse, String, String> for Recorder {
+    fn init(&mut self, _: &mut SharedState) -> Result<(), String> {
+        self.record("init");
+        match self.fail_init {
+            true => Err("No connection".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    fn handle(&mut self, event: String, _: MockContext) -> Result<String, String> {
+        self.record(&format!("handle {}", event));
+        match event.as_str() {
+            "fail" => Err("Rejected".to_string()),
+            _ => Ok(event),
+        }
+    }
+
+    fn on_error(&mut self, err: &String) -> ErrorRequest {
+        self.record(&format!("on_error {}", err));
+        ErrorRequest::new("Order.Rejected", err)
+    }
+
+    fn shutdown(&mut self) {
+        self.record("shutdown");
+    }
+}
+
+fn recording_runtime(
+    transport: &MockTransport,
+    calls: &Arc<Mutex<Vec<String>>>,
+    fail_init: bool,
+) -> MockRuntime {
+    let calls = Arc::clone(calls);
+    runtime(transport, move || Ok(Recorder { calls, fail_init }))
+}
+
+#[test]
+fn calls_the_lifecycle_methods_in_order() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", "\"ok\"")
+        .push_event("b", "\"fail\"");
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    run_to_end(&mut recording_runtime(&transport, &calls, false));
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "init",
+            "handle ok",
+            "handle fail",
+            "on_error Rejected",
+            "shutdown"
+        ]
+    );
+    transport.assert_response("a", "\"ok\"");
+    transport.assert_error("b", "Order.Rejected");
+}
+
+#[test]
+fn initializes_once_across_cycles() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", "\"ok\"")
+        .push_event("b", "\"ok\"");
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let mut runtime = recording_runtime(&transport, &calls, false);
+    runtime.run_once().unwrap();
+    runtime.run_once().unwrap();
+
+    assert_eq!(*calls.lock().unwrap(), ["init", "handle ok", "handle ok"]);
+}
+
+#[test]
+fn shuts_the_handler_down_on_request() {
+    let transport = MockTransport::new();
+    transport.push_event("a", "\"ok\"");
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let mut runtime = recording_runtime(&transport, &calls, false);
+    runtime.run_n(1).unwrap();
+    runtime.shutdown_handler();
+
+    assert_eq!(*calls.lock().unwrap(), ["init", "handle ok", "shutdown"]);
+}
+
+#[test]
+fn shuts_the_handler_down_once() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", "\"ok\"")
+        .push_event("b", "\"ok\"");
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let mut runtime = recording_runtime(&transport, &calls, false);
+    runtime.run_once().unwrap();
+    runtime.shutdown_handler();
+    runtime.shutdown_handler();
+
+    match runtime.run_once() {
+        Err(Error::Handler(error_req)) => assert!(error_req.error_message.contains("shut down")),
+        other => panic!("Expected a handler error, got {:?}", other),
+    }
+    assert_eq!(*calls.lock().unwrap(), ["init", "handle ok", "shutdown"]);
+}
+
+#[test]
+fn reports_init_failures_without_handling_events() {
+    let transport = MockTransport::new();
+    transport.push_event("a", "\"ok\"");
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let exit_codes = run_to_end(&mut recording_runtime(&transport, &calls, true));
+
+    assert_eq!(*calls.lock().unwrap(), ["init"]);
+    assert_eq!(exit_codes, vec![INIT_ERR_EXIT_CODE]);
+    transport.assert_init_error(INIT_ERR_TYPE);
+    assert!(transport.responses().is_empty());
+}
@@ -0,0 +1,117 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+#![cfg(feature = "hyper")]
+
+use Runtime_Aws_Lambda::backends::hyper::HyperTransport;
+use Runtime_Aws_Lambda::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
+use Runtime_Aws_Lambda::transport::AsyncTransport;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Serves a single HTTP/1 request with `response`, returning the head and body of the request it received.
+fn serve_once(response: &'static str) -> (String, JoinHandle<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let length = head
+            .lines()
+            .find_map(|line| {
+                line.to_lowercase()
+                    .strip_prefix("content-length: ")
+                    .map(|v| v.to_string())
+            })
+            .map_or(0, |len| len.trim().parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        stream.write_all(response.as_bytes()).unwrap();
+        (head, String::from_utf8(body).unwrap())
+    });
+    (addr, server)
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn reads_invocation_headers() {
+    let (addr, server) = serve_once(concat!(
+        "HTTP/1.1 200 OK\r\n",
+        "Lambda-Runtime-Aws-Request-Id: a\r\n",
+        "Lambda-Runtime-Deadline-Ms: 1500\r\n",
+        "Lambda-Runtime-Invoked-Function-Arn: arn:aws:lambda:us-east-1:123456789012:function:echo\r\n",
+        "Content-Length: 7\r\n",
+        "\r\n",
+        "\"hello\"",
+    ));
+
+    let resp = block_on(async {
+        let transport = HyperTransport::default();
+        transport
+            .get(&format!("http://{}/next", addr), None, None)
+            .await
+    })
+    .unwrap();
+
+    let (head, _) = server.join().unwrap();
+    assert!(head.starts_with("GET /next HTTP/1.1"));
+    assert_eq!(resp.get_status_code(), 200);
+    assert_eq!(resp.get_body(), Some("\"hello\""));
+    assert_eq!(resp.aws_request_id(), Some("a"));
+    assert_eq!(resp.deadline(), Some(Duration::from_millis(1500)));
+    assert_eq!(
+        resp.invoked_function_arn(),
+        Some("arn:aws:lambda:us-east-1:123456789012:function:echo")
+    );
+}
+
+#[test]
+fn posts_bodies_with_headers_and_returns_error_statuses() {
+    let (addr, server) = serve_once(concat!(
+        "HTTP/1.1 400 Bad Request\r\n",
+        "Content-Length: 2\r\n",
+        "\r\n",
+        "{}",
+    ));
+
+    let resp = block_on(async {
+        let transport = HyperTransport::default();
+        transport
+            .post(
+                &format!("http://{}/error", addr),
+                Some("oops"),
+                Some((vec![AWS_FUNC_ERR_TYPE], vec!["Runtime.HandlerError"])),
+            )
+            .await
+    })
+    .unwrap();
+
+    let (head, body) = server.join().unwrap();
+    assert!(head.starts_with("POST /error HTTP/1.1"));
+    assert!(head
+        .to_lowercase()
+        .contains("lambda-runtime-function-error-type: runtime.handlererror"));
+    assert_eq!(body, "oops");
+    assert_eq!(resp.get_status_code(), 400);
+    assert_eq!(resp.aws_request_id(), None);
+}
@@ -0,0 +1,125 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::MockContext;
+use Runtime_Aws_Lambda::data::context::LambdaContext;
+use Runtime_Aws_Lambda::data::identity::{ClientContext, CognitoIdentity};
+use Runtime_Aws_Lambda::data::response::{AWS_CLIENT_CTX, AWS_COG_ID};
+use Runtime_Aws_Lambda::error::Error;
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+static CLIENT_CONTEXT: &str = r#"{
+    "client": {"installation_id": "abc", "app_title": "Shop"},
+    "custom": {"cart": "42"},
+    "env": {"platform": "Android", "locale": "en_US"}
+}"#;
+
+#[test]
+fn parses_client_context() {
+    let client_context = ClientContext::parse(CLIENT_CONTEXT).unwrap();
+
+    assert_eq!(client_context.client["installation_id"], "abc");
+    assert_eq!(client_context.client["app_title"], "Shop");
+    assert_eq!(client_context.custom["cart"], "42");
+    assert_eq!(client_context.env["platform"], "Android");
+    assert_eq!(client_context.env["locale"], "en_US");
+}
+
+#[test]
+fn parses_custom_values_of_any_type() {
+    let client_context = ClientContext::parse(
+        r#"{"custom": {"items": 3, "gift": true, "coupon": {"code": "SALE", "percent": 10}}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(client_context.custom["items"], 3);
+    assert_eq!(client_context.custom["gift"], true);
+    assert_eq!(client_context.custom["coupon"]["code"], "SALE");
+    assert_eq!(client_context.custom["coupon"]["percent"], 10);
+}
+
+#[test]
+fn defaults_missing_client_context_sections() {
+    let client_context = ClientContext::parse(r#"{"custom": {"cart": "42"}}"#).unwrap();
+
+    assert!(client_context.client.is_empty());
+    assert!(client_context.env.is_empty());
+    assert_eq!(client_context.custom.len(), 1);
+}
+
+#[test]
+fn parses_cognito_identity() {
+    let expected = CognitoIdentity {
+        identity_id: "us-east-1:1234".to_string(),
+        identity_pool_id: "us-east-1:pool".to_string(),
+    };
+
+    let identity = CognitoIdentity::parse(
+        r#"{"cognitoIdentityId": "us-east-1:1234", "cognitoIdentityPoolId": "us-east-1:pool"}"#,
+    )
+    .unwrap();
+    assert_eq!(identity, expected);
+    // The snake case names used by some SDKs are accepted too
+    let identity = CognitoIdentity::parse(
+        r#"{"identity_id": "us-east-1:1234", "identity_pool_id": "us-east-1:pool"}"#,
+    )
+    .unwrap();
+    assert_eq!(identity, expected);
+}
+
+#[test]
+fn reports_the_invalid_header() {
+    match ClientContext::parse("{\"client\": ") {
+        Err(Error::InvalidHeader { header, .. }) => assert_eq!(header, AWS_CLIENT_CTX),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    match CognitoIdentity::parse("us-east-1:1234") {
+        Err(Error::InvalidHeader { header, .. }) => assert_eq!(header, AWS_COG_ID),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+/// Describes the parsed identity headers of the invocation.
+fn identify(_: String, context: MockContext) -> Result<String, String> {
+    let client_context = context.parse_client_context().map_err(|e| e.to_string())?;
+    let identity = context
+        .parse_cognito_identity()
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "{:?} {:?}",
+        client_context.map(|c| c.custom),
+        identity.map(|i| i.identity_id)
+    ))
+}
+
+#[test]
+fn parses_the_headers_of_the_invocation() {
+    let transport = MockTransport::new();
+    transport
+        .push_next(
+            MockResponse::event("a", "\"\"")
+                .client_context(r#"{"custom": {"cart": "42"}}"#)
+                .cognito_identity(r#"{"cognitoIdentityId": "us-east-1:1234"}"#),
+        )
+        .push_event("b", "\"\"")
+        .push_next(MockResponse::event("c", "\"\"").client_context("not json"));
+    let mut runtime = common::runtime(&transport, || Ok(identify));
+
+    runtime.run_n(3).unwrap();
+
+    transport.assert_response(
+        "a",
+        "\"Some({\\\"cart\\\": String(\\\"42\\\")}) Some(\\\"us-east-1:1234\\\")\"",
+    );
+    transport.assert_response("b", "\"None None\"");
+    let errors = transport.errors();
+    assert!(errors[0]
+        .body
+        .as_deref()
+        .unwrap()
+        .contains("Invalid Lambda-Runtime-Client-Context header"));
+}
@@ -0,0 +1,245 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{MockContext, MockHandler, MockRuntime, RUNTIME_API};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::error::HANDLER_ERR_TYPE;
+use Runtime_Aws_Lambda::runtime::layer::{
+    CatchPanicLayer, Layer, LoggingLayer, Next, TimingLayer, ValidateLayer,
+};
+use Runtime_Aws_Lambda::runtime::{InvocationOutcome, LambdaRuntime};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Calls = Arc<Mutex<Vec<String>>>;
+
+/// Records entering and leaving the layer, answering events equal to `short_circuit` without calling the rest of the stack.
+struct Tag {
+    name: &'static str,
+    calls: Calls,
+    short_circuit: Option<&'static str>,
+}
+
+impl Tag {
+    fn new(name: &'static str, calls: &Calls) -> Self {
+        Tag {
+            name,
+            calls: Arc::clone(calls),
+            short_circuit: None,
+        }
+    }
+}
+
+impl Layer<String, LambdaRuntimeEnv, MockResponse, String, String> for Tag {
+    fn call(
+        &mut self,
+        event: String,
+        context: MockContext,
+        next: Next<String, LambdaRuntimeEnv, MockResponse, String, String>,
+    ) -> Result<String, String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("enter {}", self.name));
+        if self.short_circuit == Some(event.as_str()) {
+            return Ok(format!("answered by {}", self.name));
+        }
+        let result = next.run(event, context);
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("leave {}", self.name));
+        result
+    }
+}
+
+/// Echoes the event, panicking for the "panic" event and failing for the "fail" event.
+fn initialize(calls: &Calls) -> impl FnOnce() -> Result<MockHandler, String> {
+    let calls = Arc::clone(calls);
+    move || {
+        Ok(Box::new(move |event: String, _| {
+            calls.lock().unwrap().push(format!("handle {}", event));
+            match event.as_str() {
+                "panic" => panic!("boom"),
+                "fail" => Err("Failed".to_string()),
+                _ => Ok(event),
+            }
+        }))
+    }
+}
+
+fn transport(events: &[&str]) -> MockTransport {
+    let transport = MockTransport::new();
+    for (id, event) in events.iter().enumerate() {
+        transport.push_event(&id.to_string(), &format!("\"{}\"", event));
+    }
+    transport
+}
+
+#[test]
+fn calls_layers_in_the_order_they_were_added() {
+    let calls = Calls::default();
+    let transport = transport(&["ok"]);
+    let mut runtime = MockRuntime::builder(initialize(&calls))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .layer(Tag::new("outer", &calls))
+        .layer(Tag::new("inner", &calls))
+        .build()
+        .unwrap();
+
+    runtime.run_once().unwrap();
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "enter outer",
+            "enter inner",
+            "handle ok",
+            "leave inner",
+            "leave outer"
+        ]
+    );
+    transport.assert_response("0", "\"ok\"");
+}
+
+#[test]
+fn short_circuits_the_rest_of_the_stack() {
+    let calls = Calls::default();
+    let transport = transport(&["cached", "ok"]);
+    let mut runtime = MockRuntime::builder(initialize(&calls))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .layer(Tag {
+            short_circuit: Some("cached"),
+            ..Tag::new("cache", &calls)
+        })
+        .layer(Tag::new("inner", &calls))
+        .build()
+        .unwrap();
+
+    runtime.run_n(2).unwrap();
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "enter cache",
+            "enter cache",
+            "enter inner",
+            "handle ok",
+            "leave inner",
+            "leave cache"
+        ]
+    );
+    transport.assert_response("0", "\"answered by cache\"");
+    transport.assert_response("1", "\"ok\"");
+}
+
+#[allow(clippy::ptr_arg)]
+fn reject_empty(event: &String) -> Result<(), String> {
+    match event.is_empty() {
+        true => Err("Empty event".to_string()),
+        false => Ok(()),
+    }
+}
+
+#[test]
+fn validate_layer_rejects_invalid_events() {
+    let calls = Calls::default();
+    let transport = transport(&["", "ok"]);
+    let mut runtime = MockRuntime::builder(initialize(&calls))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .layer(ValidateLayer::new(reject_empty))
+        .build()
+        .unwrap();
+
+    let outcomes = runtime.run_n(2).unwrap();
+
+    assert_eq!(
+        outcomes[0],
+        InvocationOutcome::HandlerError {
+            request_id: "0".to_string(),
+            error_type: HANDLER_ERR_TYPE.to_string(),
+        }
+    );
+    assert_eq!(*calls.lock().unwrap(), ["handle ok"]);
+    let errors = transport.errors();
+    assert!(errors[0].body.as_deref().unwrap().contains("Empty event"));
+}
+
+#[test]
+fn catch_panic_layer_converts_panics_into_errors() {
+    let calls = Calls::default();
+    let transport = transport(&["panic", "ok"]);
+    let mut runtime = MockRuntime::builder(initialize(&calls))
+        .env(mock_env(RUNTIME_API))
+        .transport(transport.clone())
+        .layer(CatchPanicLayer::default())
+        .build()
+        .unwrap();
+
+    let outcomes = runtime.run_n(2).unwrap();
+
+    // The panic is reported as a regular handler error rather than a `Runtime.Panic`
+    assert_eq!(
+        outcomes[0],
+        InvocationOutcome::HandlerError {
+            request_id: "0".to_string(),
+            error_type: HANDLER_ERR_TYPE.to_string(),
+        }
+    );
+    transport.assert_error("0", HANDLER_ERR_TYPE);
+    let errors = transport.errors();
+    assert!(errors[0]
+        .body
+        .as_deref()
+        .unwrap()
+        .contains("Handler panicked: boom"));
+    transport.assert_response("1", "\"ok\"");
+}
+
+static TIMINGS: Mutex<Vec<(Option<String>, Duration)>> = Mutex::new(Vec::new());
+
+fn record_timing(request_id: Option<&str>, elapsed: Duration) {
+    TIMINGS
+        .lock()
+        .unwrap()
+        .push((request_id.map(str::to_string), elapsed));
+}
+
+#[test]
+fn timing_and_logging_layers_pass_results_through() {
+    let transport = transport(&["ok", "fail"]);
+    let mut runtime = MockRuntime::builder(|| {
+        Ok(|event: String, _: MockContext| {
+            std::thread::sleep(Duration::from_millis(10));
+            match event.as_str() {
+                "fail" => Err("Failed".to_string()),
+                _ => Ok(event),
+            }
+        })
+    })
+    .env(mock_env(RUNTIME_API))
+    .transport(transport.clone())
+    .layer(TimingLayer::new(record_timing))
+    .layer(LoggingLayer)
+    .build()
+    .unwrap();
+
+    runtime.run_n(2).unwrap();
+
+    transport.assert_response("0", "\"ok\"");
+    transport.assert_error("1", HANDLER_ERR_TYPE);
+    let timings = TIMINGS.lock().unwrap();
+    let ids: Vec<_> = timings.iter().map(|(id, _)| id.as_deref()).collect();
+    assert_eq!(ids, [Some("0"), Some("1")]);
+    assert!(timings
+        .iter()
+        .all(|(_, elapsed)| *elapsed >= Duration::from_millis(10)));
+}
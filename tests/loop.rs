@@ -0,0 +1,83 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{runtime, MockHandler, MockRuntime};
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::MockTransport;
+
+fn initialize() -> Result<MockHandler, String> {
+    Ok(Box::new(|event, _| Ok(event)))
+}
+
+/// Returns a runtime whose transport serves `count` events with request ids `0`, `1`...
+fn echo_runtime(transport: &MockTransport, count: usize) -> MockRuntime {
+    for id in 0..count {
+        transport.push_event(&id.to_string(), "\"event\"");
+    }
+    runtime(transport, initialize)
+}
+
+fn next_calls(transport: &MockTransport) -> usize {
+    transport
+        .calls()
+        .iter()
+        .filter(|call| call.method == "GET")
+        .count()
+}
+
+#[test]
+fn run_n_processes_exactly_n_events() {
+    let transport = MockTransport::new();
+    let mut runtime = echo_runtime(&transport, 5);
+
+    let outcomes = runtime.run_n(3).unwrap();
+
+    let ids: Vec<_> = outcomes.iter().map(|o| o.request_id().unwrap()).collect();
+    assert_eq!(ids, ["0", "1", "2"]);
+    assert_eq!(next_calls(&transport), 3);
+    assert_eq!(transport.responses().len(), 3);
+
+    assert_eq!(runtime.run_n(0).unwrap().len(), 0);
+    assert_eq!(next_calls(&transport), 3);
+}
+
+#[test]
+fn run_until_stops_when_the_predicate_holds() {
+    let transport = MockTransport::new();
+    let mut runtime = echo_runtime(&transport, 5);
+
+    runtime
+        .run_until(|outcome| outcome.request_id() == Some("1"))
+        .unwrap();
+
+    assert_eq!(next_calls(&transport), 2);
+}
+
+#[test]
+fn shutdown_handle_stops_the_loop() {
+    let transport = MockTransport::new();
+    let mut runtime = echo_runtime(&transport, 5);
+    let handle = runtime.shutdown_handle();
+
+    // Requesting a shutdown while processing an event lets that event complete, and stops before fetching the next one
+    runtime
+        .run_until(|outcome| {
+            if outcome.request_id() == Some("1") {
+                handle.shutdown();
+            }
+            false
+        })
+        .unwrap();
+
+    assert!(handle.is_shutdown());
+    assert_eq!(next_calls(&transport), 2);
+    assert_eq!(transport.responses().len(), 2);
+
+    // The other loops return without fetching events once a shutdown was requested
+    assert!(runtime.run_n(2).unwrap().is_empty());
+    runtime.run();
+    assert_eq!(next_calls(&transport), 2);
+}
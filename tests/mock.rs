@@ -0,0 +1,101 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use Runtime_Aws_Lambda::data::env::RuntimeEnvVars;
+use Runtime_Aws_Lambda::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
+use Runtime_Aws_Lambda::error::{Error, HANDLER_ERR_TYPE};
+use Runtime_Aws_Lambda::testing::mock::{mock_env, MockResponse, MockTransport};
+use Runtime_Aws_Lambda::transport::Transport;
+
+static NEXT_URL: &str = "http://127.0.0.1:9001/2018-06-01/runtime/invocation/next";
+
+#[test]
+fn answers_next_invocations_in_order_then_with_a_container_error() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", "1")
+        .push_next_err("Connection reset");
+
+    let event = transport.get(NEXT_URL, None, None).unwrap();
+    let err = transport.get(NEXT_URL, None, None).unwrap_err();
+    let exhausted = transport.get(NEXT_URL, None, None).unwrap();
+
+    assert_eq!(event.aws_request_id(), Some("a"));
+    assert_eq!(event.get_body(), Some("1"));
+    assert!(event.deadline().is_some());
+    assert!(matches!(err, Error::Transport { .. }));
+    assert_eq!(exhausted.get_status_code(), 500);
+}
+
+#[test]
+fn accepts_posts_unless_scripted_otherwise() {
+    let transport = MockTransport::new();
+    transport.push_post(MockResponse::client_error(
+        400,
+        "InvalidRequestID",
+        "Unknown request id",
+    ));
+    let url = "http://127.0.0.1:9001/2018-06-01/runtime/invocation/a/response";
+
+    let rejected = transport.post(url, Some("1"), None).unwrap();
+    let accepted = transport.post(url, Some("2"), None).unwrap();
+
+    assert_eq!(rejected.get_status_code(), 400);
+    assert!(rejected
+        .error_response()
+        .unwrap()
+        .contains("InvalidRequestID"));
+    assert_eq!(accepted.get_status_code(), 202);
+}
+
+#[test]
+fn shares_recorded_calls_between_clones() {
+    let transport = MockTransport::new();
+    let clone = transport.clone();
+
+    clone
+        .post(
+            "http://127.0.0.1:9001/2018-06-01/runtime/invocation/a/response",
+            Some(r#"{"total": 5}"#),
+            None,
+        )
+        .unwrap();
+    clone
+        .post(
+            "http://127.0.0.1:9001/2018-06-01/runtime/invocation/b/error",
+            Some("{}"),
+            Some((vec![AWS_FUNC_ERR_TYPE], vec![HANDLER_ERR_TYPE])),
+        )
+        .unwrap();
+
+    assert_eq!(transport.calls().len(), 2);
+    assert_eq!(transport.responses()[0].request_id(), Some("a"));
+    // Bodies are compared as JSON values
+    transport.assert_response("a", r#"{"total":5}"#);
+    transport.assert_error("b", HANDLER_ERR_TYPE);
+    assert!(transport.init_errors().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Unexpected errors were posted")]
+fn fails_the_no_errors_assertion_on_posted_errors() {
+    let transport = MockTransport::new();
+    transport
+        .post(
+            "http://127.0.0.1:9001/2018-06-01/runtime/init/error",
+            Some("{}"),
+            None,
+        )
+        .unwrap();
+
+    transport.assert_no_errors();
+}
+
+#[test]
+fn creates_env_vars_without_reading_the_environment() {
+    let env = mock_env("127.0.0.1:9002");
+
+    assert_eq!(env.get_runtime_api(), Some("127.0.0.1:9002"));
+    assert_eq!(env.get_function_name(), Some("mock-function"));
+}
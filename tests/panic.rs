@@ -0,0 +1,122 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{runtime, MockContext, MockRuntime};
+use Runtime_Aws_Lambda::data::env::LambdaRuntimeEnv;
+use Runtime_Aws_Lambda::error::PANIC_ERR_TYPE;
+use Runtime_Aws_Lambda::runtime::handler::Handler;
+use Runtime_Aws_Lambda::runtime::{
+    InvocationOutcome, LambdaRuntime, CONTAINER_ERR_EXIT_CODE, PANIC_EXIT_CODE,
+};
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+use std::sync::{Arc, Mutex};
+
+type Calls = Arc<Mutex<Vec<String>>>;
+
+/// Panics for the "panic" event, recording its shutdown into `calls`.
+struct Panicky {
+    calls: Calls,
+}
+
+impl Handler<String, LambdaRuntimeEnv, MockResponse, String, String> for Panicky {
+    fn handle(&mut self, event: String, _: MockContext) -> Result<String, String> {
+        match event.as_str() {
+            "panic" => panic!("boom"),
+            _ => Ok(event),
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.calls.lock().unwrap().push("shutdown".to_string());
+    }
+}
+
+/// Returns a runtime serving a "panic" event followed by an "ok" event, recording its exit codes into `calls`.
+fn panicky_runtime(transport: &MockTransport, calls: &Calls) -> MockRuntime {
+    // Backtraces are only captured if enabled, and the setting is read once per process
+    std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    for (id, event) in ["panic", "ok"].iter().enumerate() {
+        transport.push_event(&id.to_string(), &format!("\"{}\"", event));
+    }
+    let handler_calls = Arc::clone(calls);
+    let mut runtime = runtime(transport, move || {
+        Ok(Panicky {
+            calls: handler_calls,
+        })
+    });
+    let exit_calls = Arc::clone(calls);
+    runtime.set_exit_hook(move |code| exit_calls.lock().unwrap().push(format!("exit {}", code)));
+    runtime
+}
+
+#[test]
+fn reports_panics_with_their_location_and_backtrace() {
+    let transport = MockTransport::new();
+    let mut runtime = panicky_runtime(&transport, &Calls::default());
+
+    let outcome = runtime.run_once().unwrap();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::Panicked {
+            request_id: "0".to_string(),
+            message: "boom".to_string(),
+        }
+    );
+    transport.assert_error("0", PANIC_ERR_TYPE);
+    let body: serde_json::Value =
+        serde_json::from_str(transport.errors()[0].body.as_deref().unwrap()).unwrap();
+    assert_eq!(body["errorMessage"], "boom");
+
+    let stack_trace: Vec<&str> = body["stackTrace"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|line| line.as_str().unwrap())
+        .collect();
+    assert!(stack_trace[0].starts_with("tests/panic.rs:"));
+    // The backtrace starts at the handler, without the frames of the panic hook and the panic machinery
+    assert!(stack_trace[1].contains("Panicky"), "{:?}", stack_trace);
+    assert!(!stack_trace.iter().any(|line| line.contains("install_hook")));
+}
+
+#[test]
+fn shuts_down_and_exits_after_a_panic() {
+    let transport = MockTransport::new();
+    let calls = Calls::default();
+    let mut runtime = panicky_runtime(&transport, &calls);
+
+    runtime.run();
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["shutdown".to_string(), format!("exit {}", PANIC_EXIT_CODE)]
+    );
+    transport.assert_error("0", PANIC_ERR_TYPE);
+    assert!(transport.responses().is_empty());
+}
+
+#[test]
+fn keeps_running_after_a_panic_if_configured() {
+    let transport = MockTransport::new();
+    let calls = Calls::default();
+    let mut runtime = panicky_runtime(&transport, &calls);
+    runtime.set_exit_on_panic(false);
+
+    // The loop ends once the events run out, as the transport answers with a container error
+    runtime.run();
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "shutdown".to_string(),
+            format!("exit {}", CONTAINER_ERR_EXIT_CODE)
+        ]
+    );
+    transport.assert_error("0", PANIC_ERR_TYPE);
+    transport.assert_response("1", "\"ok\"");
+}
@@ -0,0 +1,163 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{run_to_end, ExitCodes};
+use Runtime_Aws_Lambda::prelude::*;
+use Runtime_Aws_Lambda::runtime::retry::{GiveUp, RetryPolicy};
+use Runtime_Aws_Lambda::runtime::{EventHandler, InvocationOutcome, CONTAINER_ERR_EXIT_CODE};
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+use Runtime_Aws_Lambda::testing::server::{Invocation, MockRuntimeApi};
+
+use std::time::{Duration, Instant};
+
+type EchoRuntime = DefaultRuntime<
+    UreqResponse,
+    UreqTransport,
+    LambdaRuntimeEnv,
+    serde_json::Value,
+    serde_json::Value,
+    String,
+>;
+
+fn initialize() -> Result<
+    EventHandler<serde_json::Value, LambdaRuntimeEnv, UreqResponse, serde_json::Value, String>,
+    String,
+> {
+    Ok(Box::new(|event, _| Ok(event)))
+}
+
+fn initialize_mock() -> Result<common::MockHandler, String> {
+    Ok(Box::new(|event, _| Ok(event)))
+}
+
+fn runtime(api: &MockRuntimeApi, policy: RetryPolicy) -> EchoRuntime {
+    let mut runtime =
+        EchoRuntime::from_parts(LAMBDA_VER, api.env(), UreqTransport::default(), initialize);
+    runtime.set_retry_policy(policy);
+    runtime
+}
+
+fn fast_policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy::default()
+        .max_attempts(max_attempts)
+        .backoff(Duration::from_millis(1), Duration::from_millis(5))
+}
+
+#[test]
+fn retries_failed_calls_until_they_succeed() {
+    let api = MockRuntimeApi::start();
+    api.enqueue(Invocation::new("a", r#"{"n":1}"#));
+    api.fail_requests(2);
+
+    let mut runtime = runtime(&api, fast_policy(5));
+    let outcome = runtime.run_once().unwrap();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::Response {
+            request_id: "a".to_string()
+        }
+    );
+    assert_eq!(api.responses()[0].body, r#"{"n":1}"#);
+    assert!(api.received() >= 4);
+}
+
+#[test]
+fn returns_the_error_once_attempts_are_exhausted() {
+    let api = MockRuntimeApi::start();
+    api.enqueue(Invocation::new("a", "{}"));
+    api.fail_requests(100);
+
+    let mut runtime = runtime(&api, fast_policy(3));
+    let err = runtime.run_once().unwrap_err();
+
+    assert!(RetryPolicy::is_retryable(&err));
+    assert_eq!(api.pending(), 1);
+}
+
+#[test]
+fn does_not_retry_container_errors() {
+    let api = MockRuntimeApi::start();
+    api.shutdown();
+
+    let mut runtime = runtime(&api, fast_policy(5));
+    let err = runtime.run_once().unwrap_err();
+
+    assert!(err.is_container_err());
+    assert_eq!(api.received(), 1);
+}
+
+#[test]
+fn waits_between_attempts() {
+    let api = MockRuntimeApi::start();
+    api.fail_requests(100);
+
+    let policy = RetryPolicy::default()
+        .max_attempts(3)
+        .backoff(Duration::from_millis(40), Duration::from_secs(1))
+        .jitter(false);
+    let mut runtime = runtime(&api, policy);
+    let started = Instant::now();
+    assert!(runtime.run_once().is_err());
+
+    // Two backoffs of 40ms and 80ms
+    assert!(started.elapsed() >= Duration::from_millis(120));
+}
+
+#[test]
+fn exits_when_giving_up() {
+    let api = MockRuntimeApi::start();
+    api.fail_requests(100);
+    let exit_codes = ExitCodes::default();
+
+    let mut runtime = runtime(&api, fast_policy(2).give_up(GiveUp::Exit));
+    runtime.set_exit_hook(exit_codes.hook());
+    runtime.run();
+
+    assert_eq!(exit_codes.get(), vec![CONTAINER_ERR_EXIT_CODE]);
+}
+
+#[test]
+fn backoff_grows_up_to_its_maximum() {
+    let policy = RetryPolicy::default()
+        .backoff(Duration::from_millis(10), Duration::from_millis(50))
+        .jitter(false);
+
+    let backoffs: Vec<_> = (1..=5).map(|attempt| policy.backoff_for(attempt)).collect();
+    assert_eq!(
+        backoffs,
+        [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+    );
+
+    let jittered = policy.jitter(true).backoff_for(3);
+    assert!(jittered >= Duration::from_millis(20) && jittered <= Duration::from_millis(40));
+}
+
+#[test]
+fn backs_off_failed_cycles() {
+    let transport = MockTransport::new();
+    for _ in 0..3 {
+        transport.push_next(MockResponse::client_error(
+            400,
+            "InvalidRequest",
+            "Bad request",
+        ));
+    }
+    let mut runtime = common::runtime(&transport, initialize_mock);
+    runtime.set_retry_policy(
+        RetryPolicy::default()
+            .backoff(Duration::from_millis(20), Duration::from_secs(1))
+            .jitter(false),
+    );
+
+    let started = Instant::now();
+    let exit_codes = run_to_end(&mut runtime);
+
+    // Client errors are not retried, but each failed cycle backs off: 20ms, 40ms and 80ms
+    assert!(started.elapsed() >= Duration::from_millis(140));
+    assert_eq!(transport.calls().len(), 4);
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+}
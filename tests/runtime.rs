@@ -0,0 +1,197 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{run_to_end, runtime, MockContext, MockHandler, RUNTIME_API};
+use Runtime_Aws_Lambda::error::{Error, HANDLER_ERR_TYPE, INIT_ERR_TYPE, UNMARSHAL_ERR_TYPE};
+use Runtime_Aws_Lambda::runtime::{LambdaRuntime, CONTAINER_ERR_EXIT_CODE, INIT_ERR_EXIT_CODE};
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+use Runtime_Aws_Lambda::LAMBDA_VER;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct Order {
+    item: String,
+    quantity: u32,
+}
+
+#[derive(Serialize)]
+struct Receipt {
+    item: String,
+    total: u32,
+}
+
+/// Prices every item at 3, refusing empty orders.
+fn initialize() -> Result<MockHandler<Order, Receipt>, String> {
+    Ok(Box::new(|order, _| {
+        if order.quantity == 0 {
+            return Err(format!("Nothing to order for {}", order.item));
+        }
+        Ok(Receipt {
+            item: order.item,
+            total: order.quantity * 3,
+        })
+    }))
+}
+
+fn fail_initialize() -> Result<MockHandler<Order, Receipt>, String> {
+    Err("Price list unavailable".to_string())
+}
+
+/// Parses the JSON body of a posted request.
+fn json_body(body: Option<&str>) -> serde_json::Value {
+    serde_json::from_str(body.unwrap()).unwrap()
+}
+
+#[test]
+fn passes_deserialized_events_to_the_handler() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#"{"item": "apple", "quantity": 2}"#);
+
+    run_to_end(&mut runtime(&transport, initialize));
+
+    let responses = transport.responses();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(
+        responses[0].url,
+        format!(
+            "http://{}/{}/runtime/invocation/a/response",
+            RUNTIME_API, LAMBDA_VER
+        )
+    );
+    assert_eq!(
+        responses[0].body.as_deref(),
+        Some(r#"{"item":"apple","total":6}"#)
+    );
+    transport.assert_no_errors();
+}
+
+#[test]
+fn reports_events_that_fail_to_deserialize() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", r#"{"item": "apple"}"#)
+        .push_event("b", r#"{"item": "pear", "quantity": 1}"#);
+
+    run_to_end(&mut runtime(&transport, initialize));
+
+    transport.assert_error("a", UNMARSHAL_ERR_TYPE);
+    let body = json_body(transport.errors()[0].body.as_deref());
+    assert_eq!(body["errorType"], UNMARSHAL_ERR_TYPE);
+    assert!(body["errorMessage"]
+        .as_str()
+        .unwrap()
+        .contains("missing field `quantity`"));
+    // The runtime moves on to the next event
+    transport.assert_response("b", r#"{"item":"pear","total":3}"#);
+}
+
+#[test]
+fn reports_handler_errors_as_error_requests() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#"{"item": "apple", "quantity": 0}"#);
+
+    run_to_end(&mut runtime(&transport, initialize));
+
+    transport.assert_error("a", HANDLER_ERR_TYPE);
+    assert_eq!(
+        json_body(transport.errors()[0].body.as_deref()),
+        serde_json::json!({
+            "errorMessage": "Nothing to order for apple",
+            "errorType": HANDLER_ERR_TYPE,
+            "stackTrace": [],
+        })
+    );
+    assert!(transport.responses().is_empty());
+}
+
+#[test]
+fn reports_initialization_errors_and_exits() {
+    let transport = MockTransport::new();
+    transport.push_event("a", r#"{"item": "apple", "quantity": 1}"#);
+
+    // The exit hook returns, so the runtime returns instead of processing events
+    let exit_codes = run_to_end(&mut runtime(&transport, fail_initialize));
+
+    assert_eq!(exit_codes, vec![INIT_ERR_EXIT_CODE]);
+    transport.assert_init_error(INIT_ERR_TYPE);
+    let init_errors = transport.init_errors();
+    assert_eq!(
+        init_errors[0].url,
+        format!("http://{}/{}/runtime/init/error", RUNTIME_API, LAMBDA_VER)
+    );
+    let body = json_body(init_errors[0].body.as_deref());
+    assert_eq!(body["errorMessage"], "Price list unavailable");
+    assert_eq!(transport.calls().len(), 1);
+}
+
+#[test]
+fn exits_on_container_errors() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", r#"{"item": "apple", "quantity": 1}"#)
+        .push_next(MockResponse::container_error())
+        .push_event("b", r#"{"item": "pear", "quantity": 1}"#);
+
+    let exit_codes = run_to_end(&mut runtime(&transport, initialize));
+
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+    // The event following the container error is never fetched
+    let responses = transport.responses();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].request_id(), Some("a"));
+    assert_eq!(transport.calls().len(), 3);
+}
+
+#[test]
+fn continues_after_client_errors() {
+    let transport = MockTransport::new();
+    transport
+        .push_next(MockResponse::client_error(403, "Forbidden", "Not allowed"))
+        .push_event("a", r#"{"item": "apple", "quantity": 1}"#);
+
+    let exit_codes = run_to_end(&mut runtime(&transport, initialize));
+
+    // Only the container error answered once the events ran out ends the loop
+    assert_eq!(exit_codes, vec![CONTAINER_ERR_EXIT_CODE]);
+    transport.assert_response("a", r#"{"item":"apple","total":3}"#);
+    transport.assert_no_errors();
+}
+
+#[test]
+fn runs_capturing_initializers_with_stateful_handlers() {
+    let transport = MockTransport::new();
+    transport
+        .push_event("a", r#"{"item": "apple", "quantity": 1}"#)
+        .push_event("b", r#"{"item": "pear", "quantity": 1}"#);
+    // Configuration created before the runtime, moved into the initializer
+    let price = 5;
+
+    run_to_end(&mut runtime(&transport, move || {
+        let mut orders = 0;
+        Ok(move |order: Order, _: MockContext| {
+            orders += 1;
+            Ok(Receipt {
+                item: order.item,
+                total: order.quantity * price * orders,
+            })
+        })
+    }));
+
+    transport.assert_response("a", r#"{"item": "apple", "total": 5}"#);
+    transport.assert_response("b", r#"{"item": "pear", "total": 10}"#);
+}
+
+#[test]
+fn does_not_rerun_a_failed_initializer() {
+    let transport = MockTransport::new();
+    let mut runtime = runtime(&transport, fail_initialize);
+
+    assert!(matches!(runtime.run_once(), Err(Error::Handler(_))));
+    assert!(matches!(runtime.run_once(), Err(Error::Handler(_))));
+
+    assert_eq!(transport.init_errors().len(), 1);
+}
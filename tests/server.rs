@@ -0,0 +1,186 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::ExitCodes;
+use Runtime_Aws_Lambda::data::response::{LambdaAPIResponse, AWS_FUNC_ERR_TYPE};
+use Runtime_Aws_Lambda::error::{Error, HANDLER_ERR_TYPE};
+use Runtime_Aws_Lambda::prelude::*;
+use Runtime_Aws_Lambda::runtime::{EventHandler, CONTAINER_ERR_EXIT_CODE};
+use Runtime_Aws_Lambda::testing::mock::mock_env;
+use Runtime_Aws_Lambda::testing::server::{Invocation, MockRuntimeApi, PostKind};
+use Runtime_Aws_Lambda::transport::Transport;
+
+use std::io::Write;
+
+fn url(api: &MockRuntimeApi, path: &str) -> String {
+    format!("http://{}/{}/runtime/{}", api.address(), LAMBDA_VER, path)
+}
+
+#[test]
+fn serves_enqueued_events_with_their_headers() {
+    let api = MockRuntimeApi::start();
+    let arn = "arn:aws:lambda:us-east-1:123456789012:function:mock-function";
+    api.enqueue(Invocation::new("a", "\"first\"").invoked_function_arn(arn));
+    api.enqueue(Invocation::new("b", "\"second\"").deadline_ms(1500));
+    let transport = UreqTransport::default();
+
+    let first = transport
+        .get(&url(&api, "invocation/next"), None, None)
+        .unwrap();
+    let second = transport
+        .get(&url(&api, "invocation/next"), None, None)
+        .unwrap();
+
+    assert_eq!(first.get_status_code(), 200);
+    assert_eq!(first.aws_request_id(), Some("a"));
+    assert_eq!(first.get_body(), Some("\"first\""));
+    assert_eq!(first.invoked_function_arn(), Some(arn));
+    assert!(first.deadline().is_some());
+    assert_eq!(second.aws_request_id(), Some("b"));
+    assert_eq!(second.deadline().unwrap().as_millis(), 1500);
+    assert_eq!(api.pending(), 0);
+}
+
+#[test]
+fn records_posted_requests_by_kind() {
+    let api = MockRuntimeApi::start();
+    let transport = UreqTransport::default();
+
+    transport
+        .post(&url(&api, "invocation/a/response"), Some("\"ok\""), None)
+        .unwrap();
+    let headers = Some((vec![AWS_FUNC_ERR_TYPE], vec![HANDLER_ERR_TYPE]));
+    transport
+        .post(&url(&api, "invocation/b/error"), Some("{}"), headers)
+        .unwrap();
+    transport
+        .post(&url(&api, "init/error"), Some("{}"), None)
+        .unwrap();
+
+    let posted = api.posted();
+    let kinds: Vec<_> = posted.iter().map(|req| req.kind).collect();
+    assert_eq!(
+        kinds,
+        [PostKind::Response, PostKind::Error, PostKind::InitError]
+    );
+    assert_eq!(posted[0].request_id.as_deref(), Some("a"));
+    assert_eq!(posted[0].body, "\"ok\"");
+    assert_eq!(posted[1].request_id.as_deref(), Some("b"));
+    assert_eq!(posted[1].header(AWS_FUNC_ERR_TYPE), Some(HANDLER_ERR_TYPE));
+    assert_eq!(posted[2].request_id, None);
+    assert_eq!(api.responses().len(), 1);
+    assert_eq!(api.errors().len(), 1);
+    assert_eq!(api.init_errors().len(), 1);
+}
+
+#[test]
+fn records_chunked_bodies_and_trailers() {
+    let api = MockRuntimeApi::start();
+    let transport = UreqTransport::default();
+
+    let mut request = transport
+        .post_chunked(&url(&api, "invocation/a/response"), None)
+        .unwrap();
+    request.write_all(b"hello, ").unwrap();
+    request.write_all(b"world").unwrap();
+    let resp = request
+        .finish(Some((vec![AWS_FUNC_ERR_TYPE], vec![HANDLER_ERR_TYPE])))
+        .unwrap();
+
+    assert_eq!(resp.get_status_code(), 202);
+    let responses = api.responses();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].body, "hello, world");
+    assert_eq!(responses[0].header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(
+        responses[0].trailer(AWS_FUNC_ERR_TYPE),
+        Some(HANDLER_ERR_TYPE)
+    );
+}
+
+#[test]
+fn rejects_line_breaks_in_chunked_headers_and_trailers() {
+    let api = MockRuntimeApi::start();
+    let transport = UreqTransport::default();
+    let url = url(&api, "invocation/a/response");
+
+    let header = transport.post_chunked(
+        &url,
+        Some((vec!["Content-Type"], vec!["text/plain\r\nX-Injected: 1"])),
+    );
+    let request = transport.post_chunked(&url, None).unwrap();
+    let trailer = request.finish(Some((
+        vec![AWS_FUNC_ERR_TYPE],
+        vec!["Handler.Error\nX-Injected: 1"],
+    )));
+
+    assert!(matches!(header, Err(Error::Transport { .. })));
+    assert!(matches!(trailer, Err(Error::Transport { .. })));
+    assert!(api.responses().is_empty());
+}
+
+#[test]
+fn answers_with_a_container_error_once_shut_down() {
+    let api = MockRuntimeApi::start();
+    api.enqueue(Invocation::new("a", "\"ok\""));
+    let transport = UreqTransport::default();
+
+    api.shutdown();
+    let resp = transport
+        .get(&url(&api, "invocation/next"), None, None)
+        .unwrap();
+
+    assert_eq!(resp.get_status_code(), 500);
+}
+
+#[test]
+fn answers_unknown_paths_with_a_client_error() {
+    let api = MockRuntimeApi::start();
+    let transport = UreqTransport::default();
+
+    let resp = transport.post(&url(&api, "unknown"), None, None).unwrap();
+
+    assert_eq!(resp.get_status_code(), 404);
+    assert!(resp.error_response().unwrap().contains("NotFound"));
+    assert!(api.posted().is_empty());
+}
+
+type EchoRuntime =
+    DefaultRuntime<UreqResponse, UreqTransport, LambdaRuntimeEnv, String, String, String>;
+
+/// Echoes events, failing for the "fail" event.
+fn initialize(
+) -> Result<EventHandler<String, LambdaRuntimeEnv, UreqResponse, String, String>, String> {
+    Ok(Box::new(|event, _| match event.as_str() {
+        "fail" => Err("Failed".to_string()),
+        _ => Ok(event),
+    }))
+}
+
+#[test]
+fn drives_the_runtime_loop_until_shut_down() {
+    let api = MockRuntimeApi::start();
+    api.enqueue(Invocation::new("a", "\"ok\""));
+    api.enqueue(Invocation::new("b", "\"fail\""));
+    let env = mock_env(&api.address());
+    let exit_codes = ExitCodes::default();
+    let mut runtime =
+        EchoRuntime::from_parts(LAMBDA_VER, env, UreqTransport::default(), initialize);
+    runtime.set_exit_hook(exit_codes.hook());
+    // The runtime is `Send`, so it may be created here and run on another thread
+    let runner = std::thread::spawn(move || {
+        runtime.run();
+        exit_codes.get()
+    });
+    let posted = api.wait_for_posts(2, std::time::Duration::from_secs(5));
+    api.shutdown();
+
+    assert_eq!(runner.join().unwrap(), vec![CONTAINER_ERR_EXIT_CODE]);
+    let kinds: Vec<_> = posted.iter().map(|req| req.kind).collect();
+    assert_eq!(kinds, [PostKind::Response, PostKind::Error]);
+    assert_eq!(posted[0].body, "\"ok\"");
+    assert_eq!(posted[1].header(AWS_FUNC_ERR_TYPE), Some(HANDLER_ERR_TYPE));
+}
@@ -0,0 +1,118 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{run_to_end, runtime, MockHandler};
+use Runtime_Aws_Lambda::data::response::{AWS_FUNC_ERR_BODY, AWS_FUNC_RESPONSE_MODE};
+use Runtime_Aws_Lambda::error::HANDLER_ERR_TYPE;
+use Runtime_Aws_Lambda::testing::mock::{MockCall, MockTransport};
+
+use std::io::Write;
+
+/// Streams the event in two chunks, failing after the stream was started for the "fail" event.
+/// Responds without streaming to the "plain" event.
+fn initialize() -> Result<MockHandler, String> {
+    Ok(Box::new(|event, context| {
+        let mut stream = context.response_stream.unwrap();
+        match event.as_str() {
+            "plain" => Ok("not streamed".to_string()),
+            "fail" => {
+                stream.write_all(b"partial").unwrap();
+                Err("Failed mid-stream".to_string())
+            }
+            _ => {
+                stream.start("text/plain").unwrap();
+                stream.write_all(b"hello, ").unwrap();
+                stream.write_all(event.as_bytes()).unwrap();
+                Ok("ignored".to_string())
+            }
+        }
+    }))
+}
+
+/// Runs a streaming runtime over the given events, returning the posted responses.
+fn run_events(events: &[(&str, &str)]) -> Vec<MockCall> {
+    let transport = MockTransport::new();
+    for (request_id, body) in events {
+        transport.push_event(request_id, body);
+    }
+    run_to_end(&mut runtime(&transport, initialize));
+    transport.assert_no_errors();
+    transport.responses()
+}
+
+fn trailer<'a>(call: &'a MockCall, name: &str) -> Option<&'a str> {
+    call.trailers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn streams_the_response_body_in_chunks() {
+    let responses = run_events(&[("a", r#""world""#)]);
+
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].request_id(), Some("a"));
+    assert_eq!(responses[0].body.as_deref(), Some("hello, world"));
+    assert_eq!(
+        responses[0].header(AWS_FUNC_RESPONSE_MODE),
+        Some("streaming")
+    );
+    assert_eq!(responses[0].header("Content-Type"), Some("text/plain"));
+    assert_eq!(
+        responses[0].header("Trailer"),
+        Some("Lambda-Runtime-Function-Error-Type, Lambda-Runtime-Function-Error-Body")
+    );
+    assert!(responses[0].trailers.is_empty());
+}
+
+#[test]
+fn sends_errors_after_the_stream_started_in_trailers() {
+    let responses = run_events(&[("a", r#""fail""#)]);
+
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].body.as_deref(), Some("partial"));
+    assert_eq!(
+        responses[0].header("Content-Type"),
+        Some("application/octet-stream")
+    );
+    assert_eq!(responses[0].error_type(), Some(HANDLER_ERR_TYPE));
+
+    let error_body = base64_decode(trailer(&responses[0], AWS_FUNC_ERR_BODY).unwrap());
+    let error_req: serde_json::Value = serde_json::from_slice(&error_body).unwrap();
+    assert_eq!(error_req["errorType"], HANDLER_ERR_TYPE);
+    assert_eq!(error_req["errorMessage"], "Failed mid-stream");
+}
+
+#[test]
+fn responds_normally_if_the_stream_was_not_started() {
+    let responses = run_events(&[("a", r#""plain""#)]);
+
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].body.as_deref(), Some(r#""not streamed""#));
+    assert_eq!(responses[0].header(AWS_FUNC_RESPONSE_MODE), None);
+}
+
+/// Decodes standard base64 with padding, as sent in the error body trailer.
+fn base64_decode(input: &str) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    assert_eq!(input.len() % 4, 0, "unpadded base64: {}", input);
+    let mut out = Vec::new();
+    for chunk in input.as_bytes().chunks(4) {
+        let digits: Vec<u32> = chunk
+            .iter()
+            .take_while(|&&c| c != b'=')
+            .map(|c| ALPHABET.iter().position(|a| a == c).unwrap() as u32)
+            .collect();
+        let n = digits
+            .iter()
+            .chain(std::iter::repeat(&0))
+            .take(4)
+            .fold(0, |n, digit| n << 6 | digit);
+        out.extend(n.to_be_bytes()[1..digits.len()].iter());
+    }
+    out
+}
@@ -0,0 +1,110 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::{runtime, MockHandler, MockRuntime};
+use Runtime_Aws_Lambda::runtime::{InvocationOutcome, LambdaRuntime, TIMEOUT_ERR_TYPE};
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+use std::io::Write;
+use std::time::Duration;
+
+/// Sleeps for the number of milliseconds in the event.
+fn initialize() -> Result<MockHandler<u64, String>, String> {
+    Ok(Box::new(|millis, _| {
+        std::thread::sleep(Duration::from_millis(millis));
+        Ok(format!("slept {}ms", millis))
+    }))
+}
+
+/// Starts streaming its response, then sleeps for the number of milliseconds in the event.
+fn initialize_streaming() -> Result<MockHandler<u64, String>, String> {
+    Ok(Box::new(|millis, context| {
+        let mut stream = context.response_stream.unwrap();
+        stream.start("text/plain").unwrap();
+        stream.write_all(b"partial").unwrap();
+        std::thread::sleep(Duration::from_millis(millis));
+        Ok("ignored".to_string())
+    }))
+}
+
+/// Returns a runtime serving a single event sleeping `millis`, whose deadline is 150ms away and watched with a margin of 50ms.
+fn sleepy_runtime(
+    transport: &MockTransport,
+    millis: u64,
+    initializer: fn() -> Result<MockHandler<u64, String>, String>,
+) -> MockRuntime<u64, String> {
+    transport.push_next(
+        MockResponse::event("a", &millis.to_string()).timeout(Duration::from_millis(150)),
+    );
+    let mut runtime = runtime(transport, initializer);
+    runtime.set_timeout_watchdog(Some(Duration::from_millis(50)));
+    runtime
+}
+
+#[test]
+fn reports_a_single_timeout_and_discards_the_late_response() {
+    let transport = MockTransport::new();
+    let mut runtime = sleepy_runtime(&transport, 300, initialize);
+
+    let outcome = runtime.run_once().unwrap();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::TimedOut {
+            request_id: "a".to_string()
+        }
+    );
+    let errors = transport.errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].request_id(), Some("a"));
+    assert_eq!(errors[0].error_type(), Some(TIMEOUT_ERR_TYPE));
+    assert!(errors[0]
+        .body
+        .as_deref()
+        .unwrap()
+        .contains("in stage 'handler'"));
+    assert!(transport.responses().is_empty());
+}
+
+#[test]
+fn ends_started_streams_with_the_timeout_in_their_trailers() {
+    let transport = MockTransport::new();
+    let mut runtime = sleepy_runtime(&transport, 300, initialize_streaming);
+
+    let outcome = runtime.run_once().unwrap();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::TimedOut {
+            request_id: "a".to_string()
+        }
+    );
+    // The stream was already answering the invocation, so the timeout is not posted as a separate error
+    transport.assert_no_errors();
+    let responses = transport.responses();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].body.as_deref(), Some("partial"));
+    assert_eq!(responses[0].error_type(), Some(TIMEOUT_ERR_TYPE));
+}
+
+#[test]
+fn does_not_fire_for_handlers_finishing_in_time() {
+    let transport = MockTransport::new();
+    let mut runtime = sleepy_runtime(&transport, 10, initialize);
+
+    let outcome = runtime.run_once().unwrap();
+
+    assert_eq!(
+        outcome,
+        InvocationOutcome::Response {
+            request_id: "a".to_string()
+        }
+    );
+    // Give a misbehaving watchdog the chance to fire after the handler returned
+    std::thread::sleep(Duration::from_millis(200));
+    transport.assert_no_errors();
+    transport.assert_response("a", "\"slept 10ms\"");
+}
@@ -0,0 +1,66 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+mod common;
+
+use common::MockContext;
+use Runtime_Aws_Lambda::data::context::LambdaContext;
+use Runtime_Aws_Lambda::data::env::{RuntimeEnvVars, TRACE_ID_ENV_VAR};
+use Runtime_Aws_Lambda::runtime::LambdaRuntime;
+use Runtime_Aws_Lambda::testing::mock::{MockResponse, MockTransport};
+
+static TRACE_ID: &str =
+    "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+/// Answers with the trace id of the context, the env-vars instance and the env-var, in that order.
+fn trace(_: String, context: MockContext) -> Result<String, String> {
+    Ok(format!(
+        "{:?} {:?} {:?}",
+        context.trace_id(),
+        context.env_vars.get_trace_id(),
+        std::env::var(TRACE_ID_ENV_VAR).ok()
+    ))
+}
+
+/// Returns a transport serving a traced invocation followed by an untraced one.
+fn transport() -> MockTransport {
+    let transport = MockTransport::new();
+    transport
+        .push_next(MockResponse::event("a", "\"\"").trace_id(TRACE_ID))
+        .push_event("b", "\"\"");
+    transport
+}
+
+// A single test mutates the environment, as the tests of a file run concurrently in the same process
+#[test]
+fn propagates_the_trace_id_of_each_invocation() {
+    let transport = transport();
+    let mut runtime = common::runtime(&transport, || Ok(trace));
+
+    runtime.run_once().unwrap();
+    assert_eq!(runtime.get_env().get_trace_id(), Some(TRACE_ID));
+    runtime.run_once().unwrap();
+
+    let traced = format!("{:?}", Some(TRACE_ID));
+    transport.assert_response(
+        "a",
+        &serde_json::to_string(&format!("{} {} None", traced, traced)).unwrap(),
+    );
+    // The trace id of the previous invocation is cleared rather than leaking into the next one
+    transport.assert_response("b", "\"None None None\"");
+    assert_eq!(runtime.get_env().get_trace_id(), None);
+
+    runtime.set_trace_env_var(true);
+    transport
+        .push_next(MockResponse::event("c", "\"\"").trace_id(TRACE_ID))
+        .push_event("d", "\"\"");
+    runtime.run_n(2).unwrap();
+
+    transport.assert_response(
+        "c",
+        &serde_json::to_string(&format!("{} {} {}", traced, traced, traced)).unwrap(),
+    );
+    transport.assert_response("d", "\"None None None\"");
+    assert!(std::env::var(TRACE_ID_ENV_VAR).is_err());
+}
@@ -0,0 +1,241 @@
+// Copyright 2022 Guy Or and the "rtlambda" authors. All rights reserved.
+
+// `SPDX-License-Identifier: MIT OR Apache-2.0`
+
+use Runtime_Aws_Lambda::prelude::*;
+use Runtime_Aws_Lambda::runtime::xray::{XRayEmitter, XRayLayer, XRayTracer};
+use Runtime_Aws_Lambda::runtime::EventHandler;
+use Runtime_Aws_Lambda::testing::server::{Invocation, MockRuntimeApi};
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+type TracedRuntime =
+    DefaultRuntime<UreqResponse, UreqTransport, LambdaRuntimeEnv, String, String, String>;
+
+static TRACE_ID: &str =
+    "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+/// Binds a local UDP socket standing in for the X-Ray daemon.
+fn daemon() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    socket
+}
+
+/// Receives the next segment document sent to the daemon, checking the daemon header.
+fn receive(daemon: &UdpSocket) -> Option<serde_json::Value> {
+    let mut buf = [0u8; 4096];
+    let len = daemon.recv(&mut buf).ok()?;
+    let packet = std::str::from_utf8(&buf[..len]).unwrap();
+    let (header, document) = packet.split_once('\n').unwrap();
+    assert_eq!(header, "{\"format\": \"json\", \"version\": 1}");
+    Some(serde_json::from_str(document).unwrap())
+}
+
+/// Calls a fake downstream service within a subsegment of the invocation, failing for the "fail" event.
+fn initialize(
+) -> Result<EventHandler<String, LambdaRuntimeEnv, UreqResponse, String, String>, String> {
+    Ok(Box::new(|event, context| {
+        let tracer = context.extensions.get::<XRayTracer>().unwrap().clone();
+        let mut subsegment = tracer.subsegment("downstream");
+        subsegment.set_namespace("remote");
+        match event.as_str() {
+            "fail" => Err("Failed".to_string()),
+            _ => Ok(subsegment.trace_header().to_string()),
+        }
+    }))
+}
+
+fn runtime(api: &MockRuntimeApi, daemon: &UdpSocket) -> TracedRuntime {
+    let emitter = XRayEmitter::new(&format!("udp:{}", daemon.local_addr().unwrap())).unwrap();
+    TracedRuntime::builder(initialize)
+        .env(api.env())
+        .layer(XRayLayer::new(emitter))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn parses_and_formats_trace_headers() {
+    let trace: TraceHeader = TRACE_ID.parse().unwrap();
+    assert_eq!(trace.root, "1-5759e988-bd862e3fe1be46a994272793");
+    assert_eq!(trace.parent.as_deref(), Some("53995c3f42cd8ad8"));
+    assert_eq!(trace.sampled, Some(true));
+    assert_eq!(trace.to_string(), TRACE_ID);
+
+    let trace: TraceHeader = "Root=1-abc;Sampled=?;Lineage=a:1".parse().unwrap();
+    assert_eq!(trace.parent, None);
+    assert_eq!(trace.sampled, None);
+    assert_eq!(trace.to_string(), "Root=1-abc");
+
+    assert!("Parent=53995c3f42cd8ad8".parse::<TraceHeader>().is_err());
+    assert!("Root=1-abc;Sampled=yes".parse::<TraceHeader>().is_err());
+}
+
+#[test]
+fn sends_handler_and_downstream_subsegments() {
+    let (api, daemon) = (MockRuntimeApi::start(), daemon());
+    api.enqueue(Invocation::new("req-1", "\"ok\"").trace_id(TRACE_ID));
+    let mut runtime = runtime(&api, &daemon);
+
+    runtime.run_n(1).unwrap();
+
+    // The downstream subsegment ends first, nested in the handler's subsegment
+    let downstream = receive(&daemon).unwrap();
+    let handler = receive(&daemon).unwrap();
+    assert_eq!(handler["type"], "subsegment");
+    assert_eq!(handler["trace_id"], "1-5759e988-bd862e3fe1be46a994272793");
+    assert_eq!(handler["parent_id"], "53995c3f42cd8ad8");
+    assert_eq!(handler.get("fault"), None);
+    assert_eq!(downstream["name"], "downstream");
+    assert_eq!(downstream["namespace"], "remote");
+    assert_eq!(downstream["trace_id"], handler["trace_id"]);
+    assert_eq!(downstream["parent_id"], handler["id"]);
+    assert!(handler["start_time"].as_f64() <= downstream["start_time"].as_f64());
+    assert!(downstream["end_time"].as_f64() <= handler["end_time"].as_f64());
+
+    // The handler propagates the trace header of the downstream subsegment
+    let expected = format!(
+        "\"Root=1-5759e988-bd862e3fe1be46a994272793;Parent={};Sampled=1\"",
+        downstream["id"].as_str().unwrap()
+    );
+    assert_eq!(api.responses()[0].body, expected);
+}
+
+#[test]
+fn marks_failed_invocations_as_faults() {
+    let (api, daemon) = (MockRuntimeApi::start(), daemon());
+    api.enqueue(Invocation::new("req-1", "\"fail\"").trace_id(TRACE_ID));
+    let mut runtime = runtime(&api, &daemon);
+
+    runtime.run_n(1).unwrap();
+
+    let downstream = receive(&daemon).unwrap();
+    let handler = receive(&daemon).unwrap();
+    assert_eq!(downstream.get("fault"), None);
+    assert_eq!(handler["fault"], true);
+}
+
+#[test]
+fn does_not_send_unsampled_traces() {
+    let (api, daemon) = (MockRuntimeApi::start(), daemon());
+    api.enqueue(
+        Invocation::new("req-1", "\"ok\"")
+            .trace_id("Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=0"),
+    );
+    let mut runtime = runtime(&api, &daemon);
+
+    runtime.run_n(1).unwrap();
+
+    assert_eq!(receive(&daemon), None);
+    assert_eq!(api.responses().len(), 1);
+}
+
+/// A custom context type, exposing the trace id and the extensions of the context created by the runtime.
+struct RequestContext<'a> {
+    base: RefLambdaContext<'a, LambdaRuntimeEnv, UreqResponse>,
+}
+
+impl<'a> LambdaContext for RequestContext<'a> {
+    fn get_deadline(&self) -> Option<Duration> {
+        self.base.get_deadline()
+    }
+
+    fn invoked_function_arn(&self) -> Option<&str> {
+        self.base.invoked_function_arn()
+    }
+
+    fn aws_request_id(&self) -> Option<&str> {
+        self.base.aws_request_id()
+    }
+
+    fn trace_id(&self) -> Option<&str> {
+        self.base.trace_id()
+    }
+
+    fn function_name(&self) -> Option<&str> {
+        self.base.function_name()
+    }
+
+    fn function_version(&self) -> Option<&str> {
+        self.base.function_version()
+    }
+
+    fn memory_limit_in_mb(&self) -> Option<usize> {
+        self.base.memory_limit_in_mb()
+    }
+
+    fn log_group_name(&self) -> Option<&str> {
+        self.base.log_group_name()
+    }
+
+    fn log_stream_name(&self) -> Option<&str> {
+        self.base.log_stream_name()
+    }
+
+    fn cognito_identity(&self) -> Option<&str> {
+        self.base.cognito_identity()
+    }
+
+    fn client_context(&self) -> Option<&str> {
+        self.base.client_context()
+    }
+
+    fn extensions(&self) -> Option<&Extensions> {
+        self.base.extensions()
+    }
+}
+
+#[derive(Default)]
+struct RequestContextFactory;
+
+impl ContextFactory<LambdaRuntimeEnv, UreqResponse> for RequestContextFactory {
+    type Context<'a> = RequestContext<'a>;
+
+    fn create<'a>(
+        &self,
+        base: RefLambdaContext<'a, LambdaRuntimeEnv, UreqResponse>,
+    ) -> Self::Context<'a> {
+        RequestContext { base }
+    }
+}
+
+/// Answers with the trace header of the tracer inserted by the layer, whose parent is the handler's subsegment.
+fn propagate(_: String, context: RequestContext<'_>) -> Result<String, String> {
+    let extensions = context.extensions().ok_or("No extensions")?;
+    let tracer = extensions.get::<XRayTracer>().ok_or("No tracer")?;
+    Ok(tracer.trace_header().to_string())
+}
+
+#[test]
+fn traces_custom_context_types() {
+    let (api, daemon) = (MockRuntimeApi::start(), daemon());
+    api.enqueue(Invocation::new("req-1", "\"ok\"").trace_id(TRACE_ID));
+    let emitter = XRayEmitter::new(&format!("udp:{}", daemon.local_addr().unwrap())).unwrap();
+    let mut runtime = DefaultRuntime::<
+        UreqResponse,
+        UreqTransport,
+        LambdaRuntimeEnv,
+        String,
+        String,
+        String,
+        RequestContextFactory,
+    >::builder(|| Ok(propagate))
+    .env(api.env())
+    .layer(XRayLayer::new(emitter))
+    .build()
+    .unwrap();
+
+    runtime.run_n(1).unwrap();
+
+    let handler = receive(&daemon).unwrap();
+    assert_eq!(handler["trace_id"], "1-5759e988-bd862e3fe1be46a994272793");
+    let expected = format!(
+        "\"Root=1-5759e988-bd862e3fe1be46a994272793;Parent={};Sampled=1\"",
+        handler["id"].as_str().unwrap()
+    );
+    assert_eq!(api.responses()[0].body, expected);
+}